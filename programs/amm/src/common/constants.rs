@@ -0,0 +1,23 @@
+// fees are expressed in basis points of the amount put into the pool
+pub const FEE_BPS_DENOMINATOR: u64 = 10_000;
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+// share of every swap fee that goes to the protocol treasury, in basis points of the fee
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 5_000;
+
+// LP minted to the pool's locked account on the first deposit and never redeemable,
+// so the share price can't be inflated by a near empty pool
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+// keeps the observations account under the 10KiB an account can be created with
+pub const MAX_OBSERVATIONS: u16 = 250;
+
+// data_account, observations, pool_authority, lp_mint, both vaults and both mints
+pub const ROUTE_HOP_ACCOUNTS: usize = 8;
+
+// bits of the paused flags on a pool and on the config, either one pausing an operation
+// stops it, a frozen pool has all of them set
+pub const PAUSE_SWAPS: u8 = 1 << 0;
+pub const PAUSE_DEPOSITS: u8 = 1 << 1;
+pub const PAUSE_WITHDRAWALS: u8 = 1 << 2;
+pub const PAUSE_ALL: u8 = PAUSE_SWAPS | PAUSE_DEPOSITS | PAUSE_WITHDRAWALS;
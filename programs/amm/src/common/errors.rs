@@ -12,4 +12,48 @@ pub enum GeneralErrors {
     MathUnderflow,
     #[msg("divide by 0")]
     MathDivisionByZero,
+    #[msg("Both sides of the pool use the same mint")]
+    IdenticalMints,
+    #[msg("Output amount is below the requested minimum")]
+    SlippageExceeded,
+    #[msg("Input amount is above the requested maximum")]
+    ExcessiveInputAmount,
+    #[msg("Fee is above the allowed maximum")]
+    FeeTooHigh,
+    #[msg("Signer is not allowed to perform this action")]
+    Unauthorized,
+    #[msg("Pool has no liquidity")]
+    PoolEmpty,
+    #[msg("Deposit is too small to mint any LP tokens")]
+    InsufficientLiquidityMinted,
+    #[msg("Initial deposit does not cover the minimum locked liquidity")]
+    InitialLiquidityTooLow,
+    #[msg("Withdrawn amount is below the requested minimum")]
+    WithdrawalBelowMinimum,
+    #[msg("Transaction deadline has passed")]
+    Expired,
+    #[msg("Observation capacity must be between 1 and MAX_OBSERVATIONS")]
+    InvalidObservationCapacity,
+    #[msg("Not enough price history for this window")]
+    NotEnoughObservations,
+    #[msg("Callback program cannot be the AMM itself")]
+    InvalidCallbackProgram,
+    #[msg("Flash swap was not paid back with enough tokens")]
+    InvariantViolated,
+    #[msg("Mint is not one of the pool tokens")]
+    MintNotInPool,
+    #[msg("Pool has an outstanding flash loan")]
+    FlashLoanActive,
+    #[msg("Pool has no outstanding flash loan")]
+    NoFlashLoan,
+    #[msg("Flash loan is not repaid later in the transaction")]
+    FlashLoanNotRepaid,
+    #[msg("Flash loans cannot be borrowed through CPI")]
+    FlashLoanFromCpi,
+    #[msg("Route accounts do not chain distinct pools")]
+    InvalidRoute,
+    #[msg("This operation is paused")]
+    Paused,
+    #[msg("Unknown pause flags")]
+    InvalidPauseFlags,
 }
@@ -0,0 +1,81 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct PoolCreated {
+    pub pool: Pubkey,
+    pub creator: Pubkey,
+    pub token_1_mint: Pubkey,
+    pub token_2_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub fee_bps: u16,
+}
+
+#[event]
+pub struct LiquidityAdded {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_1_amount: u64,
+    pub token_2_amount: u64,
+    pub lp_minted: u64,
+    pub lp_locked: u64,
+    pub token_1_balance: u64,
+    pub token_2_balance: u64,
+    pub lp_supply: u64,
+}
+
+#[event]
+pub struct LiquidityRemoved {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_1_amount: u64,
+    pub token_2_amount: u64,
+    pub lp_burned: u64,
+    pub token_1_balance: u64,
+    pub token_2_balance: u64,
+    pub lp_supply: u64,
+}
+
+// fee is the whole swap fee charged on amount_in, protocol_fee is the part of it
+// set aside for the treasury
+#[event]
+pub struct Swapped {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_in: Pubkey,
+    pub token_out: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+    pub protocol_fee: u64,
+    pub token_1_balance: u64,
+    pub token_2_balance: u64,
+    pub lp_supply: u64,
+}
+
+// amounts in are what the callback paid back into each vault, fees are charged on them
+#[event]
+pub struct FlashSwapped {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub callback_program: Pubkey,
+    pub token_1_out: u64,
+    pub token_2_out: u64,
+    pub token_1_in: u64,
+    pub token_2_in: u64,
+    pub token_1_fee: u64,
+    pub token_2_fee: u64,
+    pub token_1_balance: u64,
+    pub token_2_balance: u64,
+}
+
+// fee is what the borrower paid on top of amount, it is added to the pool reserves
+#[event]
+pub struct FlashLoanRepaid {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    pub token_1_balance: u64,
+    pub token_2_balance: u64,
+}
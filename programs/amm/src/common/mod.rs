@@ -1,4 +1,7 @@
+pub mod constants;
 pub mod errors;
+pub mod events;
 
+pub use constants::*;
 pub use errors::*;
-
+pub use events::*;
@@ -0,0 +1,15 @@
+use anchor_lang::prelude::*;
+
+use crate::{DataAccount, GeneralErrors};
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub pending_admin: Signer<'info>,
+    #[account(
+        seeds=[b"dataAccount", data_account.token_1_mint.as_ref(), data_account.token_2_mint.as_ref()],
+        bump = data_account.bump,
+        has_one = pending_admin @ GeneralErrors::Unauthorized,
+        mut
+    )]
+    pub data_account: Account<'info, DataAccount>,
+}
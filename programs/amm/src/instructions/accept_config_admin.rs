@@ -0,0 +1,15 @@
+use anchor_lang::prelude::*;
+
+use crate::{Config, GeneralErrors};
+
+#[derive(Accounts)]
+pub struct AcceptConfigAdmin<'info> {
+    pub pending_admin: Signer<'info>,
+    #[account(
+        seeds=[b"config"],
+        bump = config.bump,
+        has_one = pending_admin @ GeneralErrors::Unauthorized,
+        mut
+    )]
+    pub config: Account<'info, Config>,
+}
@@ -4,25 +4,39 @@ use anchor_spl::{
     token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
-use crate::DataAccount;
+use crate::{Config, DataAccount, GeneralErrors, PAUSE_DEPOSITS};
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct AddLiquidity<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
     #[account(
-        seeds=[b"dataAccount",  crate::ID.as_ref()],
-        bump,
+        seeds=[b"dataAccount", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.bump,
+        constraint = data_account.flash_loan_amount == 0 @ GeneralErrors::FlashLoanActive,
         mut
     )]
     pub data_account: Account<'info, DataAccount>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = ((config.paused | data_account.paused) & (PAUSE_DEPOSITS)) == 0 @ GeneralErrors::Paused,
+    )]
+    pub config: Account<'info, Config>,
     pub system_program: Program<'info, System>,
     #[account(
-        seeds = [b"mint"],
-        bump,
+        seeds = [b"mint", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.lp_mint_bump,
         mut
     )]
     pub lp_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"locked_lp", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.locked_lp_bump,
+        mut
+    )]
+    pub locked_lp_account: InterfaceAccount<'info, TokenAccount>,
     pub token_program: Interface<'info, TokenInterface>,
     #[account(
         associated_token::mint = mint_token1,
@@ -44,8 +58,8 @@ pub struct AddLiquidity<'info> {
     pub mint_token2: InterfaceAccount<'info, Mint>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     #[account(
-        seeds = [b"pool_authority"],
-        bump,
+        seeds = [b"pool_authority", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.pool_authority_bump,
     )]
     pub pool_authority: SystemAccount<'info>,
     #[account(
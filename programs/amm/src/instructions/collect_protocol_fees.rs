@@ -0,0 +1,68 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+use crate::{Config, DataAccount, GeneralErrors};
+
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        seeds=[b"dataAccount", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.bump,
+        constraint = data_account.flash_loan_amount == 0 @ GeneralErrors::FlashLoanActive,
+        mut
+    )]
+    pub data_account: Account<'info, DataAccount>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = treasury,
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: only used as the owner of the treasury token accounts, pinned by config
+    pub treasury: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    #[account(
+        associated_token::mint = mint_token1,
+        associated_token::authority = pool_authority,
+        associated_token::token_program = token_program,
+        mut
+    )]
+    pub token_1_account: InterfaceAccount<'info, TokenAccount>,
+    pub mint_token1: InterfaceAccount<'info, Mint>,
+    #[account(
+        associated_token::mint = mint_token2,
+        associated_token::authority = pool_authority,
+        associated_token::token_program = token_program,
+        mut
+    )]
+    pub token_2_account: InterfaceAccount<'info, TokenAccount>,
+    pub mint_token2: InterfaceAccount<'info, Mint>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(
+        seeds = [b"pool_authority", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.pool_authority_bump,
+    )]
+    pub pool_authority: SystemAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint_token1,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program,
+    )]
+    pub treasury_token_1_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint_token2,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program,
+    )]
+    pub treasury_token_2_account: InterfaceAccount<'info, TokenAccount>,
+}
@@ -3,8 +3,61 @@ use anchor_lang::prelude::*;
 #[account]
 pub struct DataAccount {
     pub bump: u8,
+    pub lp_mint_bump: u8,
+    pub pool_authority_bump: u8,
+    pub locked_lp_bump: u8,
     pub token_1_mint: Pubkey,
     pub token_2_mint: Pubkey,
     pub token_1_balance: u64,
     pub token_2_balance: u64,
+    pub fee_bps: u16,
+    pub protocol_fees_token_1: u64,
+    pub protocol_fees_token_2: u64,
+    // time weighted sums of the Q64.64 price of token 1 in token 2 and of token 2 in token 1,
+    // they wrap on overflow so readers must diff two samples with wrapping_sub
+    pub price_1_cumulative: u128,
+    pub price_2_cumulative: u128,
+    pub last_price_update: i64,
+    // a non zero flash_loan_amount means a flash loan of flash_loan_mint is outstanding and
+    // flash_loan_amount plus flash_loan_fee has to come back before the transaction ends
+    pub flash_loan_mint: Pubkey,
+    pub flash_loan_amount: u64,
+    pub flash_loan_fee: u64,
+    // admin governs the pool, pending_admin is who it has been offered to and only takes
+    // over once it accepts, the default pubkey means no transfer is pending
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+    // PAUSE_* bits set by the pool admin
+    pub paused: u8,
+}
+
+#[account]
+pub struct Config {
+    pub bump: u8,
+    pub admin: Pubkey,
+    // offered the admin seat by propose_config_admin, default when there is no offer
+    pub pending_admin: Pubkey,
+    pub treasury: Pubkey,
+    pub protocol_fee_bps: u16,
+    pub flash_loan_fee_bps: u16,
+    // PAUSE_* bits set by the config admin, they apply to every pool
+    pub paused: u8,
+}
+
+// ring buffer of the pool's cumulative prices, index points at the latest sample
+// and the vec grows up to capacity before it starts overwriting the oldest one
+#[account]
+pub struct Observations {
+    pub bump: u8,
+    pub pool: Pubkey,
+    pub capacity: u16,
+    pub index: u16,
+    pub observations: Vec<Observation>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct Observation {
+    pub timestamp: i64,
+    pub price_1_cumulative: u128,
+    pub price_2_cumulative: u128,
 }
@@ -0,0 +1,85 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+use crate::{Config, DataAccount, GeneralErrors, Observations, PAUSE_DEPOSITS, PAUSE_SWAPS};
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct DepositSingleToken<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        seeds=[b"dataAccount", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.bump,
+        constraint = data_account.flash_loan_amount == 0 @ GeneralErrors::FlashLoanActive,
+        mut
+    )]
+    pub data_account: Account<'info, DataAccount>,
+    #[account(
+        seeds=[b"observations", data_account.key().as_ref()],
+        bump = observations.bump,
+        mut
+    )]
+    pub observations: Account<'info, Observations>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = ((config.paused | data_account.paused) & (PAUSE_DEPOSITS | PAUSE_SWAPS)) == 0 @ GeneralErrors::Paused,
+    )]
+    pub config: Account<'info, Config>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        seeds = [b"mint", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.lp_mint_bump,
+        mut
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    #[account(
+        associated_token::mint = mint_token1,
+        associated_token::authority = pool_authority,
+        associated_token::token_program = token_program,
+        mut
+    )]
+    pub token_1_account: InterfaceAccount<'info, TokenAccount>,
+    pub mint_token1: InterfaceAccount<'info, Mint>,
+    #[account(
+        associated_token::mint = mint_token2,
+        associated_token::authority = pool_authority,
+        associated_token::token_program = token_program,
+        mut
+    )]
+    pub token_2_account: InterfaceAccount<'info, TokenAccount>,
+    pub mint_token2: InterfaceAccount<'info, Mint>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(
+        seeds = [b"pool_authority", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.pool_authority_bump,
+    )]
+    pub pool_authority: SystemAccount<'info>,
+    #[account(
+        associated_token::mint = mint_token1,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+        mut
+    )]
+    pub token_1_account_of_user: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        associated_token::mint = mint_token2,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+        mut
+    )]
+    pub token_2_account_of_user: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = lp_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+    )]
+    pub user_lp_ata: InterfaceAccount<'info, TokenAccount>,
+}
@@ -0,0 +1,55 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions as sysvar_instructions;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+use crate::{Config, DataAccount};
+
+// shared by flash_loan_borrow and flash_loan_repay, data_account has to stay the second
+// account since the borrow finds its repay by looking for the pool at that position
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FlashLoan<'info> {
+    pub signer: Signer<'info>,
+    #[account(
+        seeds=[b"dataAccount", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.bump,
+        mut
+    )]
+    pub data_account: Account<'info, DataAccount>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
+    pub token_program: Interface<'info, TokenInterface>,
+    #[account(
+        associated_token::mint = mint_token1,
+        associated_token::authority = pool_authority,
+        associated_token::token_program = token_program,
+        mut
+    )]
+    pub token_1_account: InterfaceAccount<'info, TokenAccount>,
+    pub mint_token1: InterfaceAccount<'info, Mint>,
+    #[account(
+        associated_token::mint = mint_token2,
+        associated_token::authority = pool_authority,
+        associated_token::token_program = token_program,
+        mut
+    )]
+    pub token_2_account: InterfaceAccount<'info, TokenAccount>,
+    pub mint_token2: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"pool_authority", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.pool_authority_bump,
+    )]
+    pub pool_authority: SystemAccount<'info>,
+    // receives the loan on borrow and pays it back on repay, signer has to own it for the latter
+    #[account(
+        token::token_program = token_program,
+        mut
+    )]
+    pub borrower_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: address is the instructions sysvar
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
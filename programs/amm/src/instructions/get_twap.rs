@@ -0,0 +1,24 @@
+use anchor_lang::prelude::*;
+
+use crate::{DataAccount, Observations};
+
+#[derive(Accounts)]
+pub struct GetTwap<'info> {
+    #[account(
+        seeds=[b"dataAccount", data_account.token_1_mint.as_ref(), data_account.token_2_mint.as_ref()],
+        bump = data_account.bump,
+    )]
+    pub data_account: Account<'info, DataAccount>,
+    #[account(
+        seeds=[b"observations", data_account.key().as_ref()],
+        bump = observations.bump,
+    )]
+    pub observations: Account<'info, Observations>,
+}
+
+// average Q64.64 price of token 1 in token 2 and of token 2 in token 1 over the window
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct Twap {
+    pub price_1: u128,
+    pub price_2: u128,
+}
@@ -4,20 +4,36 @@ use anchor_spl::{
     token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
-use crate::DataAccount;
+use crate::{Config, DataAccount, GeneralErrors, Observations};
 
+#[event_cpi]
 #[derive(Accounts)]
+#[instruction(fee_bps: u16, observation_capacity: u16)]
 pub struct Initialize<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
+    // anyone can open a pool, its admin seat goes to the config admin
+    #[account(
+        seeds=[b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
     #[account(
         init,
         payer=signer,
-        space=8+1+32+32+8+8,
-        seeds=[b"dataAccount",  crate::ID.as_ref()],
+        space=8+1+1+1+1+32+32+8+8+2+8+8+16+16+8+32+8+8+32+32+1,
+        seeds=[b"dataAccount", mint_token1.key().min(mint_token2.key()).as_ref(), mint_token1.key().max(mint_token2.key()).as_ref()],
         bump
     )]
     pub data_account: Account<'info, DataAccount>,
+    #[account(
+        init,
+        payer=signer,
+        space=8+1+32+2+2+4+(8+16+16)*observation_capacity as usize,
+        seeds=[b"observations", data_account.key().as_ref()],
+        bump
+    )]
+    pub observations: Account<'info, Observations>,
     pub system_program: Program<'info, System>,
     #[account(
         init,
@@ -25,10 +41,21 @@ pub struct Initialize<'info> {
         mint::decimals = 6,
         mint::authority = lp_mint.key(),
         mint::freeze_authority = lp_mint.key(),
-        seeds = [b"mint"],
+        seeds = [b"mint", mint_token1.key().min(mint_token2.key()).as_ref(), mint_token1.key().max(mint_token2.key()).as_ref()],
         bump
     )]
     pub lp_mint: InterfaceAccount<'info, Mint>,
+    // holds the MINIMUM_LIQUIDITY minted on the first deposit, nothing ever moves it out
+    #[account(
+        init,
+        payer = signer,
+        token::mint = lp_mint,
+        token::authority = pool_authority,
+        token::token_program = token_program,
+        seeds = [b"locked_lp", mint_token1.key().min(mint_token2.key()).as_ref(), mint_token1.key().max(mint_token2.key()).as_ref()],
+        bump
+    )]
+    pub locked_lp_account: InterfaceAccount<'info, TokenAccount>,
     pub token_program: Interface<'info, TokenInterface>,
     #[account(
         init_if_needed,
@@ -47,10 +74,15 @@ pub struct Initialize<'info> {
         associated_token::token_program = token_program,
     )]
     pub token_2_account: InterfaceAccount<'info, TokenAccount>,
+    // the mints may come in either order, every seed above uses them sorted ascending so
+    // A/B and B/A can only ever map to one pool
+    #[account(
+        constraint = mint_token1.key() != mint_token2.key() @ GeneralErrors::IdenticalMints,
+    )]
     pub mint_token2: InterfaceAccount<'info, Mint>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     #[account(
-        seeds = [b"pool_authority"],
+        seeds = [b"pool_authority", mint_token1.key().min(mint_token2.key()).as_ref(), mint_token1.key().max(mint_token2.key()).as_ref()],
         bump
     )]
     pub pool_authority: SystemAccount<'info>,
@@ -0,0 +1,25 @@
+use anchor_lang::prelude::*;
+
+use crate::{program::Amm, Config, GeneralErrors};
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        init,
+        payer=signer,
+        space=8+1+32+32+32+2+2+1,
+        seeds=[b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+    pub system_program: Program<'info, System>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, Amm>,
+    // only the upgrade authority can claim the admin seat
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(signer.key()) @ GeneralErrors::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+}
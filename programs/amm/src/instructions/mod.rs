@@ -1,13 +1,43 @@
+pub mod accept_admin;
+pub mod accept_config_admin;
 pub mod add_liquidity;
+pub mod collect_protocol_fees;
 pub mod data_accounts;
+pub mod deposit_single_token;
+pub mod flash_loan;
+pub mod flash_swap;
+pub mod get_twap;
 pub mod initialize;
+pub mod initialize_config;
+pub mod pool_admin;
 pub mod quote;
+pub mod quote_liquidity;
 pub mod remove_liquidity;
+pub mod skim;
 pub mod swap;
+pub mod swap_route;
+pub mod sync;
+pub mod update_config;
+pub mod withdraw_single_token;
 
+pub use accept_admin::*;
+pub use accept_config_admin::*;
 pub use add_liquidity::*;
+pub use collect_protocol_fees::*;
 pub use data_accounts::*;
+pub use deposit_single_token::*;
+pub use flash_loan::*;
+pub use flash_swap::*;
+pub use get_twap::*;
 pub use initialize::*;
+pub use initialize_config::*;
+pub use pool_admin::*;
 pub use quote::*;
+pub use quote_liquidity::*;
 pub use remove_liquidity::*;
+pub use skim::*;
 pub use swap::*;
+pub use swap_route::*;
+pub use sync::*;
+pub use update_config::*;
+pub use withdraw_single_token::*;
@@ -0,0 +1,33 @@
+use anchor_lang::prelude::*;
+
+use crate::{Config, DataAccount, GeneralErrors};
+
+#[derive(Accounts)]
+pub struct PoolAdmin<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds=[b"dataAccount", data_account.token_1_mint.as_ref(), data_account.token_2_mint.as_ref()],
+        bump = data_account.bump,
+        has_one = admin @ GeneralErrors::Unauthorized,
+        mut
+    )]
+    pub data_account: Account<'info, DataAccount>,
+}
+
+// the config admin acting on a single pool, whoever holds the pool's own admin seat
+#[derive(Accounts)]
+pub struct ProtocolPoolAdmin<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds=[b"config"],
+        bump = config.bump,
+        has_one = admin @ GeneralErrors::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        seeds=[b"dataAccount", data_account.token_1_mint.as_ref(), data_account.token_2_mint.as_ref()],
+        bump = data_account.bump,
+        mut
+    )]
+    pub data_account: Account<'info, DataAccount>,
+}
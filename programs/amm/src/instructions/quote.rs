@@ -1,8 +1,29 @@
 use anchor_lang::prelude::*;
 
-use crate::DataAccount;
+use crate::{Config, DataAccount};
 
 #[derive(Accounts)]
 pub struct QuoteAmm<'info> {
+    #[account(
+        seeds=[b"dataAccount", data_account.token_1_mint.as_ref(), data_account.token_2_mint.as_ref()],
+        bump = data_account.bump,
+    )]
     pub data_account: Account<'info, DataAccount>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+// effective_price is amount_out per amount_in as a Q64.64 fixed point number
+// price_impact_bps is how far that price is below the pre-trade spot price, fees excluded
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SwapQuote {
+    pub amount_out: u64,
+    pub fee: u64,
+    pub effective_price: u128,
+    pub price_impact_bps: u64,
+    pub token_1_balance_after: u64,
+    pub token_2_balance_after: u64,
 }
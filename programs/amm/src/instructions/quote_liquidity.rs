@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::DataAccount;
+
+#[derive(Accounts)]
+pub struct QuoteLiquidity<'info> {
+    #[account(
+        seeds=[b"dataAccount", data_account.token_1_mint.as_ref(), data_account.token_2_mint.as_ref()],
+        bump = data_account.bump,
+    )]
+    pub data_account: Account<'info, DataAccount>,
+    #[account(
+        seeds = [b"mint", data_account.token_1_mint.as_ref(), data_account.token_2_mint.as_ref()],
+        bump = data_account.lp_mint_bump,
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+}
+
+// token_1_amount and token_2_amount are what add_liquidity would actually pull,
+// lp_amount is what the depositor gets and lp_locked what the first deposit locks
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AddLiquidityQuote {
+    pub token_1_amount: u64,
+    pub token_2_amount: u64,
+    pub lp_amount: u64,
+    pub lp_locked: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct RemoveLiquidityQuote {
+    pub token_1_amount: u64,
+    pub token_2_amount: u64,
+}
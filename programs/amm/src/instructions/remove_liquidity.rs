@@ -4,22 +4,30 @@ use anchor_spl::{
     token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
-use crate::DataAccount;
+use crate::{Config, DataAccount, GeneralErrors, PAUSE_WITHDRAWALS};
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct RemoveLiquidity<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
     #[account(
-        seeds=[b"dataAccount",  crate::ID.as_ref()],
-        bump,
+        seeds=[b"dataAccount", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.bump,
+        constraint = data_account.flash_loan_amount == 0 @ GeneralErrors::FlashLoanActive,
         mut
     )]
     pub data_account: Account<'info, DataAccount>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = ((config.paused | data_account.paused) & (PAUSE_WITHDRAWALS)) == 0 @ GeneralErrors::Paused,
+    )]
+    pub config: Account<'info, Config>,
     pub system_program: Program<'info, System>,
     #[account(
-        seeds = [b"mint"],
-        bump,
+        seeds = [b"mint", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.lp_mint_bump,
         mut
     )]
     pub lp_mint: InterfaceAccount<'info, Mint>,
@@ -44,8 +52,8 @@ pub struct RemoveLiquidity<'info> {
     pub mint_token2: InterfaceAccount<'info, Mint>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     #[account(
-        seeds = [b"pool_authority"],
-        bump,
+        seeds = [b"pool_authority", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.pool_authority_bump,
         mut
     )]
     pub pool_authority: SystemAccount<'info>,
@@ -0,0 +1,49 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+use crate::{DataAccount, GeneralErrors};
+
+#[derive(Accounts)]
+pub struct SkimPool<'info> {
+    pub signer: Signer<'info>,
+    #[account(
+        seeds=[b"dataAccount", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.bump,
+        constraint = data_account.flash_loan_amount == 0 @ GeneralErrors::FlashLoanActive,
+    )]
+    pub data_account: Account<'info, DataAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    #[account(
+        associated_token::mint = mint_token1,
+        associated_token::authority = pool_authority,
+        associated_token::token_program = token_program,
+        mut
+    )]
+    pub token_1_account: InterfaceAccount<'info, TokenAccount>,
+    pub mint_token1: InterfaceAccount<'info, Mint>,
+    #[account(
+        associated_token::mint = mint_token2,
+        associated_token::authority = pool_authority,
+        associated_token::token_program = token_program,
+        mut
+    )]
+    pub token_2_account: InterfaceAccount<'info, TokenAccount>,
+    pub mint_token2: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"pool_authority", mint_token1.key().as_ref(), mint_token2.key().as_ref()],
+        bump = data_account.pool_authority_bump,
+    )]
+    pub pool_authority: SystemAccount<'info>,
+    #[account(
+        token::mint = mint_token1,
+        token::token_program = token_program,
+        mut
+    )]
+    pub recipient_token_1_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        token::mint = mint_token2,
+        token::token_program = token_program,
+        mut
+    )]
+    pub recipient_token_2_account: InterfaceAccount<'info, TokenAccount>,
+}
@@ -0,0 +1,42 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+use crate::{Config, DataAccount, GeneralErrors, Observations, PAUSE_SWAPS};
+
+// the pools of the route come in as remaining accounts, ROUTE_HOP_ACCOUNTS per hop
+// in the order of RouteHop's fields
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SwapRoute<'info> {
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = (config.paused & PAUSE_SWAPS) == 0 @ GeneralErrors::Paused,
+    )]
+    pub config: Account<'info, Config>,
+    pub token_program: Interface<'info, TokenInterface>,
+    #[account(
+        token::authority = signer,
+        token::token_program = token_program,
+        mut
+    )]
+    pub user_source_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        token::token_program = token_program,
+        mut
+    )]
+    pub user_destination_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+// one pool of a route, loaded by load_route_hops
+pub struct RouteHop<'info> {
+    pub data_account: Account<'info, DataAccount>,
+    pub observations: Account<'info, Observations>,
+    pub pool_authority: AccountInfo<'info>,
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+    pub token_1_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_2_account: InterfaceAccount<'info, TokenAccount>,
+    pub mint_token1: InterfaceAccount<'info, Mint>,
+    pub mint_token2: InterfaceAccount<'info, Mint>,
+}
@@ -1,5 +1,17 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::{self, burn_checked, BurnChecked, MintTo, TransferChecked};
+use anchor_lang::solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program::invoke,
+    sysvar::instructions::{
+        get_instruction_relative, load_current_index_checked, load_instruction_at_checked,
+    },
+};
+use anchor_spl::{
+    associated_token::get_associated_token_address_with_program_id,
+    token_interface::{
+        self, burn_checked, BurnChecked, Mint, MintTo, TokenAccount, TransferChecked,
+    },
+};
 
 declare_id!("Avj3EdWetSP4wZwMG5xCn9zWKCb9cq7EQVd5xVotyJDj");
 
@@ -13,51 +25,225 @@ pub use instructions::*;
 pub mod amm {
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+    // fee_bps is the swap fee of this pool in basis points
+    // observation_capacity is how many price samples the pool keeps for get_twap
+    pub fn initialize(
+        ctx: Context<Initialize>,
+        fee_bps: u16,
+        observation_capacity: u16,
+    ) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, GeneralErrors::FeeTooHigh);
+        require!(
+            observation_capacity > 0 && observation_capacity <= MAX_OBSERVATIONS,
+            GeneralErrors::InvalidObservationCapacity
+        );
         ctx.accounts.data_account.bump = ctx.bumps.data_account;
-        ctx.accounts.data_account.token_1_mint = ctx.accounts.mint_token1.key();
-        ctx.accounts.data_account.token_2_mint = ctx.accounts.mint_token2.key();
+        ctx.accounts.data_account.lp_mint_bump = ctx.bumps.lp_mint;
+        ctx.accounts.data_account.pool_authority_bump = ctx.bumps.pool_authority;
+        ctx.accounts.data_account.locked_lp_bump = ctx.bumps.locked_lp_account;
+        let (token_1_mint, token_2_mint) = sort_mints(
+            ctx.accounts.mint_token1.key(),
+            ctx.accounts.mint_token2.key(),
+        );
+        ctx.accounts.data_account.token_1_mint = token_1_mint;
+        ctx.accounts.data_account.token_2_mint = token_2_mint;
         ctx.accounts.data_account.token_1_balance = 0;
         ctx.accounts.data_account.token_2_balance = 0;
+        ctx.accounts.data_account.fee_bps = fee_bps;
+        ctx.accounts.data_account.protocol_fees_token_1 = 0;
+        ctx.accounts.data_account.protocol_fees_token_2 = 0;
+        ctx.accounts.data_account.price_1_cumulative = 0;
+        ctx.accounts.data_account.price_2_cumulative = 0;
+        ctx.accounts.data_account.last_price_update = Clock::get()?.unix_timestamp;
+        ctx.accounts.data_account.flash_loan_amount = 0;
+        ctx.accounts.data_account.flash_loan_fee = 0;
+        ctx.accounts.data_account.admin = ctx.accounts.config.admin;
+        ctx.accounts.data_account.pending_admin = Pubkey::default();
+        ctx.accounts.data_account.paused = 0;
+        ctx.accounts.observations.bump = ctx.bumps.observations;
+        ctx.accounts.observations.pool = ctx.accounts.data_account.key();
+        ctx.accounts.observations.capacity = observation_capacity;
+        ctx.accounts.observations.index = 0;
+        ctx.accounts.observations.observations = Vec::with_capacity(observation_capacity as usize);
+        write_observation(&mut ctx.accounts.observations, &ctx.accounts.data_account);
+        emit_cpi!(PoolCreated {
+            pool: ctx.accounts.data_account.key(),
+            creator: ctx.accounts.signer.key(),
+            token_1_mint,
+            token_2_mint,
+            lp_mint: ctx.accounts.lp_mint.key(),
+            fee_bps,
+        });
+        Ok(())
+    }
+
+    // protocol_fee_bps is the share of every swap fee kept for the treasury
+    // flash_loan_fee_bps is charged on every flash loan and left to the LPs
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        treasury: Pubkey,
+        protocol_fee_bps: u16,
+        flash_loan_fee_bps: u16,
+    ) -> Result<()> {
+        require!(
+            protocol_fee_bps <= MAX_PROTOCOL_FEE_BPS && flash_loan_fee_bps <= MAX_FEE_BPS,
+            GeneralErrors::FeeTooHigh
+        );
+        ctx.accounts.config.bump = ctx.bumps.config;
+        ctx.accounts.config.admin = ctx.accounts.signer.key();
+        ctx.accounts.config.pending_admin = Pubkey::default();
+        ctx.accounts.config.treasury = treasury;
+        ctx.accounts.config.protocol_fee_bps = protocol_fee_bps;
+        ctx.accounts.config.flash_loan_fee_bps = flash_loan_fee_bps;
+        ctx.accounts.config.paused = 0;
+        Ok(())
+    }
+
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        treasury: Pubkey,
+        protocol_fee_bps: u16,
+        flash_loan_fee_bps: u16,
+    ) -> Result<()> {
+        require!(
+            protocol_fee_bps <= MAX_PROTOCOL_FEE_BPS && flash_loan_fee_bps <= MAX_FEE_BPS,
+            GeneralErrors::FeeTooHigh
+        );
+        ctx.accounts.config.treasury = treasury;
+        ctx.accounts.config.protocol_fee_bps = protocol_fee_bps;
+        ctx.accounts.config.flash_loan_fee_bps = flash_loan_fee_bps;
+        Ok(())
+    }
+
+    // offers the config admin seat to new_admin, who has to accept_config_admin before it
+    // counts, proposing the default pubkey withdraws a pending offer
+    pub fn propose_config_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        ctx.accounts.config.pending_admin = new_admin;
+        Ok(())
+    }
+
+    pub fn accept_config_admin(ctx: Context<AcceptConfigAdmin>) -> Result<()> {
+        ctx.accounts.config.admin = ctx.accounts.pending_admin.key();
+        ctx.accounts.config.pending_admin = Pubkey::default();
+        Ok(())
+    }
+
+    // offers the pool's admin seat to new_admin, who has to accept_admin before it counts,
+    // proposing the default pubkey withdraws a pending offer
+    pub fn propose_admin(ctx: Context<PoolAdmin>, new_admin: Pubkey) -> Result<()> {
+        ctx.accounts.data_account.pending_admin = new_admin;
+        Ok(())
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        ctx.accounts.data_account.admin = ctx.accounts.pending_admin.key();
+        ctx.accounts.data_account.pending_admin = Pubkey::default();
+        Ok(())
+    }
+
+    pub fn update_pool_fee(ctx: Context<PoolAdmin>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, GeneralErrors::FeeTooHigh);
+        ctx.accounts.data_account.fee_bps = fee_bps;
+        Ok(())
+    }
+
+    // paused is a combination of PAUSE_SWAPS and PAUSE_DEPOSITS and replaces the pool's current
+    // ones, withdrawals are left to the config admin so a pool admin can never lock LPs in
+    pub fn set_pool_pause(ctx: Context<PoolAdmin>, paused: u8) -> Result<()> {
+        require!(paused & !PAUSE_ALL == 0, GeneralErrors::InvalidPauseFlags);
+        require!(paused & PAUSE_WITHDRAWALS == 0, GeneralErrors::Unauthorized);
+        let withdrawals = ctx.accounts.data_account.paused & PAUSE_WITHDRAWALS;
+        ctx.accounts.data_account.paused = paused | withdrawals;
+        Ok(())
+    }
+
+    // sets any of the pool's PAUSE_* flags, including the ones its pool admin can't touch
+    pub fn override_pool_pause(ctx: Context<ProtocolPoolAdmin>, paused: u8) -> Result<()> {
+        require!(paused & !PAUSE_ALL == 0, GeneralErrors::InvalidPauseFlags);
+        ctx.accounts.data_account.paused = paused;
+        Ok(())
+    }
+
+    // same as set_pool_pause but for every pool at once
+    pub fn set_global_pause(ctx: Context<UpdateConfig>, paused: u8) -> Result<()> {
+        require!(paused & !PAUSE_ALL == 0, GeneralErrors::InvalidPauseFlags);
+        ctx.accounts.config.paused = paused;
+        Ok(())
+    }
+
+    // sends everything the protocol has accrued in this pool to the treasury
+    pub fn collect_protocol_fees(ctx: Context<CollectProtocolFees>) -> Result<()> {
+        let token1_to_collect = ctx.accounts.data_account.protocol_fees_token_1;
+        let token2_to_collect = ctx.accounts.data_account.protocol_fees_token_2;
+        let mint_token1_key = ctx.accounts.mint_token1.key();
+        let mint_token2_key = ctx.accounts.mint_token2.key();
+        let seeds: &[&[&[u8]]] = &[&[
+            b"pool_authority",
+            mint_token1_key.as_ref(),
+            mint_token2_key.as_ref(),
+            &[ctx.accounts.data_account.pool_authority_bump],
+        ]];
+        if token1_to_collect > 0 {
+            transfer_tokens_general_from_pool_to_user(
+                ctx.accounts.mint_token1.to_account_info(),
+                ctx.accounts.token_1_account.to_account_info(),
+                ctx.accounts.treasury_token_1_account.to_account_info(),
+                ctx.accounts.pool_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                token1_to_collect,
+                ctx.accounts.mint_token1.decimals,
+                seeds,
+            )?;
+        }
+        if token2_to_collect > 0 {
+            transfer_tokens_general_from_pool_to_user(
+                ctx.accounts.mint_token2.to_account_info(),
+                ctx.accounts.token_2_account.to_account_info(),
+                ctx.accounts.treasury_token_2_account.to_account_info(),
+                ctx.accounts.pool_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                token2_to_collect,
+                ctx.accounts.mint_token2.decimals,
+                seeds,
+            )?;
+        }
+        ctx.accounts.data_account.protocol_fees_token_1 = 0;
+        ctx.accounts.data_account.protocol_fees_token_2 = 0;
         Ok(())
     }
 
+    // max_token1_amount and max_token2_amount are the most the caller is willing to deposit,
+    // the side that binds at the current pool ratio is used in full and the other is scaled
+    // min_lp_out is the least LP the caller accepts back, the deposit reverts below it
     pub fn add_liquidity(
         ctx: Context<AddLiquidity>,
-        token1_amount: u64,
-        token2_amount: u64,
+        max_token1_amount: u64,
+        max_token2_amount: u64,
+        min_lp_out: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
+        check_deadline(deadline)?;
         require!(
-            token1_amount > 0 && token2_amount > 0,
+            max_token1_amount > 0 && max_token2_amount > 0,
             GeneralErrors::InsufficientAmount
         );
-        let token1_amount_to_add_to_pool = token1_amount;
-        let token2_amount_to_add_to_pool: u64;
-        let amount_to_mint: u64;
-        if ctx.accounts.data_account.token_1_balance == 0 {
-            token2_amount_to_add_to_pool = token2_amount;
-            amount_to_mint = calc_first_lp_mint(token1_amount, token2_amount);
-        } else {
-            let required_first_token = ctx.accounts.data_account.token_1_balance;
-            let prev_amount_2 = ctx.accounts.data_account.token_2_balance;
-            let required_token2_for_all_token1 = token1_amount
-                .checked_mul(prev_amount_2)
-                .unwrap()
-                .checked_div(required_first_token)
-                .unwrap();
-            token2_amount_to_add_to_pool = required_token2_for_all_token1;
-            require!(
-                token2_amount_to_add_to_pool <= token2_amount,
-                GeneralErrors::InsufficientAmount
-            );
-            amount_to_mint = calc_subsequent_lp_mint(
-                token1_amount_to_add_to_pool,
-                token2_amount_to_add_to_pool,
-                ctx.accounts.data_account.token_1_balance,
-                ctx.accounts.data_account.token_2_balance,
-                ctx.accounts.lp_mint.supply,
-            )
-        }
+        let (
+            token1_amount_to_add_to_pool,
+            token2_amount_to_add_to_pool,
+            amount_to_mint,
+            amount_to_lock,
+        ) = calc_add_liquidity(
+            max_token1_amount,
+            max_token2_amount,
+            ctx.accounts.data_account.token_1_balance,
+            ctx.accounts.data_account.token_2_balance,
+            ctx.accounts.lp_mint.supply,
+        )?;
+        require!(
+            amount_to_mint >= min_lp_out,
+            GeneralErrors::SlippageExceeded
+        );
+        update_price_cumulative(&mut ctx.accounts.data_account)?;
         transfer_tokens_general_from_user_to_pool(
             ctx.accounts.mint_token1.to_account_info(),
             ctx.accounts.token_1_account_of_user.to_account_info(),
@@ -76,7 +262,14 @@ pub mod amm {
             token2_amount_to_add_to_pool,
             ctx.accounts.mint_token2.decimals,
         )?;
-        let signer_seeds: &[&[&[u8]]] = &[&[b"mint", &[ctx.bumps.lp_mint]]];
+        let mint_token1_key = ctx.accounts.mint_token1.key();
+        let mint_token2_key = ctx.accounts.mint_token2.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"mint",
+            mint_token1_key.as_ref(),
+            mint_token2_key.as_ref(),
+            &[ctx.accounts.data_account.lp_mint_bump],
+        ]];
         mint_lp_tokens(
             ctx.accounts.lp_mint.to_account_info(),
             ctx.accounts.user_lp_ata.to_account_info(),
@@ -85,27 +278,342 @@ pub mod amm {
             amount_to_mint,
             signer_seeds,
         )?;
-        ctx.accounts.data_account.token_1_balance += token1_amount_to_add_to_pool;
-        ctx.accounts.data_account.token_2_balance += token2_amount_to_add_to_pool;
+        if amount_to_lock > 0 {
+            mint_lp_tokens(
+                ctx.accounts.lp_mint.to_account_info(),
+                ctx.accounts.locked_lp_account.to_account_info(),
+                ctx.accounts.lp_mint.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                amount_to_lock,
+                signer_seeds,
+            )?;
+        }
+        ctx.accounts.data_account.token_1_balance = ctx
+            .accounts
+            .data_account
+            .token_1_balance
+            .checked_add(token1_amount_to_add_to_pool)
+            .ok_or(GeneralErrors::MathOverflow)?;
+        ctx.accounts.data_account.token_2_balance = ctx
+            .accounts
+            .data_account
+            .token_2_balance
+            .checked_add(token2_amount_to_add_to_pool)
+            .ok_or(GeneralErrors::MathOverflow)?;
+        emit_cpi!(LiquidityAdded {
+            pool: ctx.accounts.data_account.key(),
+            user: ctx.accounts.signer.key(),
+            token_1_amount: token1_amount_to_add_to_pool,
+            token_2_amount: token2_amount_to_add_to_pool,
+            lp_minted: amount_to_mint,
+            lp_locked: amount_to_lock,
+            token_1_balance: ctx.accounts.data_account.token_1_balance,
+            token_2_balance: ctx.accounts.data_account.token_2_balance,
+            lp_supply: ctx.accounts.lp_mint.supply + amount_to_mint + amount_to_lock,
+        });
+        Ok(())
+    }
+
+    // deposits amount of token alone, part of it is swapped for the other token first so the
+    // rest and the swap output go in at the pool ratio, whatever of the swap output the ratio
+    // can't take is paid back to the user
+    // min_lp_out is the least LP the caller accepts back, the deposit reverts below it
+    pub fn deposit_single_token(
+        ctx: Context<DepositSingleToken>,
+        token: Pubkey,
+        amount: u64,
+        min_lp_out: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+        require!(amount > 0, GeneralErrors::InsufficientAmount);
+        let token1_mint = ctx.accounts.data_account.token_1_mint;
+        let token1_balance = ctx.accounts.data_account.token_1_balance;
+        let token2_balance = ctx.accounts.data_account.token_2_balance;
+        let fee_bps = ctx.accounts.data_account.fee_bps;
+        let is_token_1 = token == token1_mint;
+        require!(
+            is_token_1 || token == ctx.accounts.data_account.token_2_mint,
+            GeneralErrors::MintNotInPool
+        );
+        require!(
+            token1_balance > 0 && token2_balance > 0,
+            GeneralErrors::PoolEmpty
+        );
+        let reserve_in = if is_token_1 {
+            token1_balance
+        } else {
+            token2_balance
+        };
+        let swap_amount = calc_single_sided_swap(amount, reserve_in, fee_bps)?;
+        let swap_out = get_swap_quote(
+            token1_balance,
+            token2_balance,
+            token1_mint,
+            after_fee(swap_amount, fee_bps)?,
+            token,
+        )?;
+        require!(swap_out > 0, GeneralErrors::InsufficientAmount);
+        update_price_cumulative(&mut ctx.accounts.data_account)?;
+        write_observation(&mut ctx.accounts.observations, &ctx.accounts.data_account);
+        let (fee, protocol_fee) = book_swap(
+            &mut ctx.accounts.data_account,
+            token,
+            swap_amount,
+            swap_out,
+            ctx.accounts.config.protocol_fee_bps,
+        )?;
+        let swapped = Swapped {
+            pool: ctx.accounts.data_account.key(),
+            user: ctx.accounts.signer.key(),
+            token_in: token,
+            token_out: if is_token_1 {
+                ctx.accounts.data_account.token_2_mint
+            } else {
+                token1_mint
+            },
+            amount_in: swap_amount,
+            amount_out: swap_out,
+            fee,
+            protocol_fee,
+            token_1_balance: ctx.accounts.data_account.token_1_balance,
+            token_2_balance: ctx.accounts.data_account.token_2_balance,
+            lp_supply: ctx.accounts.lp_mint.supply,
+        };
+        let rest = amount - swap_amount;
+        let (max_token1_amount, max_token2_amount) = if is_token_1 {
+            (rest, swap_out)
+        } else {
+            (swap_out, rest)
+        };
+        let (token1_amount_to_add_to_pool, token2_amount_to_add_to_pool, amount_to_mint, _) =
+            calc_add_liquidity(
+                max_token1_amount,
+                max_token2_amount,
+                ctx.accounts.data_account.token_1_balance,
+                ctx.accounts.data_account.token_2_balance,
+                ctx.accounts.lp_mint.supply,
+            )?;
+        require!(
+            amount_to_mint >= min_lp_out,
+            GeneralErrors::SlippageExceeded
+        );
+        let accounts = &ctx.accounts;
+        let (mint_in, vault_in, user_in, deposit_in) = if is_token_1 {
+            (
+                &accounts.mint_token1,
+                &accounts.token_1_account,
+                &accounts.token_1_account_of_user,
+                token1_amount_to_add_to_pool,
+            )
+        } else {
+            (
+                &accounts.mint_token2,
+                &accounts.token_2_account,
+                &accounts.token_2_account_of_user,
+                token2_amount_to_add_to_pool,
+            )
+        };
+        let (mint_out, vault_out, user_out, deposit_out) = if is_token_1 {
+            (
+                &accounts.mint_token2,
+                &accounts.token_2_account,
+                &accounts.token_2_account_of_user,
+                token2_amount_to_add_to_pool,
+            )
+        } else {
+            (
+                &accounts.mint_token1,
+                &accounts.token_1_account,
+                &accounts.token_1_account_of_user,
+                token1_amount_to_add_to_pool,
+            )
+        };
+        // the swap output never leaves the vault, only the part the deposit can't use does
+        transfer_tokens_general_from_user_to_pool(
+            mint_in.to_account_info(),
+            user_in.to_account_info(),
+            vault_in.to_account_info(),
+            accounts.signer.to_account_info(),
+            accounts.token_program.to_account_info(),
+            swap_amount + deposit_in,
+            mint_in.decimals,
+        )?;
+        let mint_token1_key = accounts.mint_token1.key();
+        let mint_token2_key = accounts.mint_token2.key();
+        if swap_out > deposit_out {
+            let seeds: &[&[&[u8]]] = &[&[
+                b"pool_authority",
+                mint_token1_key.as_ref(),
+                mint_token2_key.as_ref(),
+                &[accounts.data_account.pool_authority_bump],
+            ]];
+            transfer_tokens_general_from_pool_to_user(
+                mint_out.to_account_info(),
+                vault_out.to_account_info(),
+                user_out.to_account_info(),
+                accounts.pool_authority.to_account_info(),
+                accounts.token_program.to_account_info(),
+                swap_out - deposit_out,
+                mint_out.decimals,
+                seeds,
+            )?;
+        }
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"mint",
+            mint_token1_key.as_ref(),
+            mint_token2_key.as_ref(),
+            &[accounts.data_account.lp_mint_bump],
+        ]];
+        mint_lp_tokens(
+            accounts.lp_mint.to_account_info(),
+            accounts.user_lp_ata.to_account_info(),
+            accounts.lp_mint.to_account_info(),
+            accounts.token_program.to_account_info(),
+            amount_to_mint,
+            signer_seeds,
+        )?;
+        ctx.accounts.data_account.token_1_balance = ctx
+            .accounts
+            .data_account
+            .token_1_balance
+            .checked_add(token1_amount_to_add_to_pool)
+            .ok_or(GeneralErrors::MathOverflow)?;
+        ctx.accounts.data_account.token_2_balance = ctx
+            .accounts
+            .data_account
+            .token_2_balance
+            .checked_add(token2_amount_to_add_to_pool)
+            .ok_or(GeneralErrors::MathOverflow)?;
+        emit_cpi!(swapped);
+        emit_cpi!(LiquidityAdded {
+            pool: ctx.accounts.data_account.key(),
+            user: ctx.accounts.signer.key(),
+            token_1_amount: token1_amount_to_add_to_pool,
+            token_2_amount: token2_amount_to_add_to_pool,
+            lp_minted: amount_to_mint,
+            lp_locked: 0,
+            token_1_balance: ctx.accounts.data_account.token_1_balance,
+            token_2_balance: ctx.accounts.data_account.token_2_balance,
+            lp_supply: ctx.accounts.lp_mint.supply + amount_to_mint,
+        });
         Ok(())
     }
 
     // token means token you are giving to the pool
     // amount of tokens to send to amm
-    pub fn quote(ctx: Context<QuoteAmm>, token: Pubkey, amount: u64) -> Result<u64> {
+    pub fn quote(ctx: Context<QuoteAmm>, token: Pubkey, amount: u64) -> Result<SwapQuote> {
+        require!(
+            token == ctx.accounts.data_account.token_1_mint
+                || token == ctx.accounts.data_account.token_2_mint,
+            GeneralErrors::MintNotInPool
+        );
+        let token1_mint = ctx.accounts.data_account.token_1_mint;
+        let token1_balance = ctx.accounts.data_account.token_1_balance;
+        let token2_balance = ctx.accounts.data_account.token_2_balance;
+        let fee_bps = ctx.accounts.data_account.fee_bps;
+        get_quote_details(
+            token1_balance,
+            token2_balance,
+            token1_mint,
+            amount,
+            token,
+            fee_bps,
+            ctx.accounts.config.protocol_fee_bps,
+        )
+    }
+
+    // token means token you want to receive from the pool
+    // amount of tokens to receive, returns what has to be sent to the amm
+    pub fn quote_exact_out(ctx: Context<QuoteAmm>, token: Pubkey, amount: u64) -> Result<u64> {
+        require!(
+            token == ctx.accounts.data_account.token_1_mint
+                || token == ctx.accounts.data_account.token_2_mint,
+            GeneralErrors::MintNotInPool
+        );
         let token1_mint = ctx.accounts.data_account.token_1_mint;
         let token1_balance = ctx.accounts.data_account.token_1_balance;
         let token2_balance = ctx.accounts.data_account.token_2_balance;
-        get_quote(token1_balance, token2_balance, token1_mint, amount, token)
+        let fee_bps = ctx.accounts.data_account.fee_bps;
+        get_quote_exact_out(
+            token1_balance,
+            token2_balance,
+            token1_mint,
+            amount,
+            token,
+            fee_bps,
+        )
+    }
+
+    // average prices over the last window_seconds, as Q64.64 fixed point numbers
+    pub fn get_twap(ctx: Context<GetTwap>, window_seconds: u32) -> Result<Twap> {
+        let now = Clock::get()?.unix_timestamp;
+        let (price_1, price_2) = calc_twap(
+            &ctx.accounts.data_account,
+            &ctx.accounts.observations,
+            now,
+            window_seconds,
+        )?;
+        Ok(Twap { price_1, price_2 })
+    }
+
+    // token1_amount and token2_amount are the maximums add_liquidity would be called with
+    pub fn quote_add_liquidity(
+        ctx: Context<QuoteLiquidity>,
+        token1_amount: u64,
+        token2_amount: u64,
+    ) -> Result<AddLiquidityQuote> {
+        require!(
+            token1_amount > 0 && token2_amount > 0,
+            GeneralErrors::InsufficientAmount
+        );
+        let (token_1_amount, token_2_amount, lp_amount, lp_locked) = calc_add_liquidity(
+            token1_amount,
+            token2_amount,
+            ctx.accounts.data_account.token_1_balance,
+            ctx.accounts.data_account.token_2_balance,
+            ctx.accounts.lp_mint.supply,
+        )?;
+        Ok(AddLiquidityQuote {
+            token_1_amount,
+            token_2_amount,
+            lp_amount,
+            lp_locked,
+        })
+    }
+
+    pub fn quote_remove_liquidity(
+        ctx: Context<QuoteLiquidity>,
+        lp_amount: u64,
+    ) -> Result<RemoveLiquidityQuote> {
+        let (token_1_amount, token_2_amount) = tokens_to_return_while_remove_liquidity(
+            lp_amount,
+            ctx.accounts.lp_mint.supply,
+            ctx.accounts.data_account.token_1_balance,
+            ctx.accounts.data_account.token_2_balance,
+        )?;
+        Ok(RemoveLiquidityQuote {
+            token_1_amount,
+            token_2_amount,
+        })
     }
 
     // amount you want to put into the pool
+    // minimum_amount_out is the least the caller accepts back, the swap reverts below it
     pub fn swap(
         ctx: Context<SwapToken>,
         amount_adding_to_pool: u64,
         token_putting_to_pool: Pubkey,
+        minimum_amount_out: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
-        let amount_after_fee = after_fee(amount_adding_to_pool)?;
+        check_deadline(deadline)?;
+        require!(
+            token_putting_to_pool == ctx.accounts.data_account.token_1_mint
+                || token_putting_to_pool == ctx.accounts.data_account.token_2_mint,
+            GeneralErrors::MintNotInPool
+        );
+        let amount_after_fee = after_fee(amount_adding_to_pool, ctx.accounts.data_account.fee_bps)?;
         let token1_balance = ctx.accounts.data_account.token_1_balance;
         let token2_balance = ctx.accounts.data_account.token_2_balance;
         let token1_mint = ctx.accounts.data_account.token_1_mint;
@@ -116,62 +624,448 @@ pub mod amm {
             amount_after_fee,
             token_putting_to_pool,
         )?;
-        let seeds: &[&[&[u8]]] = &[&[b"pool_authority", &[ctx.bumps.pool_authority]]];
-        if token_putting_to_pool == ctx.accounts.data_account.token_1_mint.key() {
-            transfer_tokens_general_from_user_to_pool(
+        require!(
+            amount_to_send_to_user >= minimum_amount_out,
+            GeneralErrors::SlippageExceeded
+        );
+        let event = settle_swap(
+            ctx.accounts,
+            token_putting_to_pool,
+            amount_adding_to_pool,
+            amount_to_send_to_user,
+        )?;
+        emit_cpi!(event);
+        Ok(())
+    }
+
+    // amount_out is exactly what the user receives of token_receiving
+    // maximum_amount_in is the most the caller is willing to pay for it, the swap reverts above it
+    pub fn swap_exact_out(
+        ctx: Context<SwapToken>,
+        amount_out: u64,
+        maximum_amount_in: u64,
+        token_receiving: Pubkey,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+        require!(
+            token_receiving == ctx.accounts.data_account.token_1_mint
+                || token_receiving == ctx.accounts.data_account.token_2_mint,
+            GeneralErrors::MintNotInPool
+        );
+        require!(amount_out > 0, GeneralErrors::InsufficientAmount);
+        let token1_balance = ctx.accounts.data_account.token_1_balance;
+        let token2_balance = ctx.accounts.data_account.token_2_balance;
+        let token1_mint = ctx.accounts.data_account.token_1_mint;
+        let amount_in_after_fee = get_swap_quote_exact_out(
+            token1_balance,
+            token2_balance,
+            token1_mint,
+            amount_out,
+            token_receiving,
+        )?;
+        let amount_adding_to_pool =
+            before_fee(amount_in_after_fee, ctx.accounts.data_account.fee_bps)?;
+        require!(
+            amount_adding_to_pool <= maximum_amount_in,
+            GeneralErrors::ExcessiveInputAmount
+        );
+        let token_putting_to_pool = if token_receiving == token1_mint {
+            ctx.accounts.data_account.token_2_mint
+        } else {
+            token1_mint
+        };
+        let event = settle_swap(
+            ctx.accounts,
+            token_putting_to_pool,
+            amount_adding_to_pool,
+            amount_out,
+        )?;
+        emit_cpi!(event);
+        Ok(())
+    }
+
+    // sends token1_amount_out and token2_amount_out to the recipients first, then invokes
+    // callback_program with data and the remaining accounts so it can pay for them, the
+    // vaults have to hold enough afterwards for the constant product to survive the fees
+    pub fn flash_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashSwap<'info>>,
+        token1_amount_out: u64,
+        token2_amount_out: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        require!(
+            token1_amount_out > 0 || token2_amount_out > 0,
+            GeneralErrors::InsufficientAmount
+        );
+        let token1_reserve = ctx.accounts.data_account.token_1_balance;
+        let token2_reserve = ctx.accounts.data_account.token_2_balance;
+        require!(
+            token1_amount_out < token1_reserve && token2_amount_out < token2_reserve,
+            GeneralErrors::PoolInsufficient
+        );
+        update_price_cumulative(&mut ctx.accounts.data_account)?;
+        write_observation(&mut ctx.accounts.observations, &ctx.accounts.data_account);
+        let mint_token1_key = ctx.accounts.mint_token1.key();
+        let mint_token2_key = ctx.accounts.mint_token2.key();
+        let seeds: &[&[&[u8]]] = &[&[
+            b"pool_authority",
+            mint_token1_key.as_ref(),
+            mint_token2_key.as_ref(),
+            &[ctx.accounts.data_account.pool_authority_bump],
+        ]];
+        if token1_amount_out > 0 {
+            transfer_tokens_general_from_pool_to_user(
                 ctx.accounts.mint_token1.to_account_info(),
-                ctx.accounts.token_1_account_of_user.to_account_info(),
                 ctx.accounts.token_1_account.to_account_info(),
-                ctx.accounts.signer.to_account_info(),
+                ctx.accounts.recipient_token_1_account.to_account_info(),
+                ctx.accounts.pool_authority.to_account_info(),
                 ctx.accounts.token_program.to_account_info(),
-                amount_adding_to_pool,
+                token1_amount_out,
                 ctx.accounts.mint_token1.decimals,
+                seeds,
             )?;
+        }
+        if token2_amount_out > 0 {
             transfer_tokens_general_from_pool_to_user(
                 ctx.accounts.mint_token2.to_account_info(),
                 ctx.accounts.token_2_account.to_account_info(),
-                ctx.accounts.token_2_account_of_user.to_account_info(),
+                ctx.accounts.recipient_token_2_account.to_account_info(),
                 ctx.accounts.pool_authority.to_account_info(),
                 ctx.accounts.token_program.to_account_info(),
-                amount_to_send_to_user,
+                token2_amount_out,
                 ctx.accounts.mint_token2.decimals,
                 seeds,
             )?;
-            ctx.accounts.data_account.token_1_balance += amount_adding_to_pool;
-            ctx.accounts.data_account.token_2_balance -= amount_to_send_to_user;
-        } else {
-            transfer_tokens_general_from_user_to_pool(
-                ctx.accounts.mint_token2.to_account_info(),
-                ctx.accounts.token_2_account_of_user.to_account_info(),
-                ctx.accounts.token_2_account.to_account_info(),
-                ctx.accounts.signer.to_account_info(),
+        }
+        invoke_flash_callback(
+            ctx.accounts.callback_program.to_account_info(),
+            ctx.remaining_accounts,
+            data,
+        )?;
+        ctx.accounts.token_1_account.reload()?;
+        ctx.accounts.token_2_account.reload()?;
+        let (token1_in_vault, token2_in_vault) = vault_balances_owned_by_lps(
+            &ctx.accounts.data_account,
+            ctx.accounts.token_1_account.amount,
+            ctx.accounts.token_2_account.amount,
+        );
+        let (token1_in, token2_in, token1_fee, token2_fee) = check_flash_swap_invariant(
+            token1_reserve,
+            token2_reserve,
+            token1_amount_out,
+            token2_amount_out,
+            token1_in_vault,
+            token2_in_vault,
+            ctx.accounts.data_account.fee_bps,
+        )?;
+        let protocol_fee_bps = ctx.accounts.config.protocol_fee_bps;
+        let token1_protocol_fee = protocol_fee_share(token1_fee, protocol_fee_bps)?;
+        let token2_protocol_fee = protocol_fee_share(token2_fee, protocol_fee_bps)?;
+        let data_account = &mut ctx.accounts.data_account;
+        data_account.token_1_balance = token1_in_vault
+            .checked_sub(token1_protocol_fee)
+            .ok_or(GeneralErrors::MathUnderflow)?;
+        data_account.token_2_balance = token2_in_vault
+            .checked_sub(token2_protocol_fee)
+            .ok_or(GeneralErrors::MathUnderflow)?;
+        data_account.protocol_fees_token_1 = data_account
+            .protocol_fees_token_1
+            .checked_add(token1_protocol_fee)
+            .ok_or(GeneralErrors::MathOverflow)?;
+        data_account.protocol_fees_token_2 = data_account
+            .protocol_fees_token_2
+            .checked_add(token2_protocol_fee)
+            .ok_or(GeneralErrors::MathOverflow)?;
+        emit_cpi!(FlashSwapped {
+            pool: ctx.accounts.data_account.key(),
+            user: ctx.accounts.signer.key(),
+            callback_program: ctx.accounts.callback_program.key(),
+            token_1_out: token1_amount_out,
+            token_2_out: token2_amount_out,
+            token_1_in: token1_in,
+            token_2_in: token2_in,
+            token_1_fee: token1_fee,
+            token_2_fee: token2_fee,
+            token_1_balance: ctx.accounts.data_account.token_1_balance,
+            token_2_balance: ctx.accounts.data_account.token_2_balance,
+        });
+        Ok(())
+    }
+
+    // swaps amount_in of the source account's token through the pools of the route in order,
+    // intermediate amounts go vault to vault and only the last pool pays the user
+    // minimum_amount_out is the least the caller accepts from the last pool
+    pub fn swap_route<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapRoute<'info>>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+        require!(amount_in > 0, GeneralErrors::InsufficientAmount);
+        let mut hops = load_route_hops(ctx.remaining_accounts, &ctx.accounts.token_program.key())?;
+        // (token in, amount in, amount out) of every hop
+        let mut legs = Vec::with_capacity(hops.len());
+        let mut token = ctx.accounts.user_source_token_account.mint;
+        let mut amount = amount_in;
+        for hop in hops.iter() {
+            let pool = &hop.data_account;
+            require!(
+                token == pool.token_1_mint || token == pool.token_2_mint,
+                GeneralErrors::MintNotInPool
+            );
+            let amount_out = get_swap_quote(
+                pool.token_1_balance,
+                pool.token_2_balance,
+                pool.token_1_mint,
+                after_fee(amount, pool.fee_bps)?,
+                token,
+            )?;
+            legs.push((token, amount, amount_out));
+            token = if token == pool.token_1_mint {
+                pool.token_2_mint
+            } else {
+                pool.token_1_mint
+            };
+            amount = amount_out;
+        }
+        require!(
+            amount >= minimum_amount_out,
+            GeneralErrors::SlippageExceeded
+        );
+        require_keys_eq!(
+            ctx.accounts.user_destination_token_account.mint,
+            token,
+            GeneralErrors::MintNotInPool
+        );
+        let ((first_vault, first_mint), _) = route_hop_sides(&hops[0], legs[0].0);
+        transfer_tokens_general_from_user_to_pool(
+            first_mint.to_account_info(),
+            ctx.accounts.user_source_token_account.to_account_info(),
+            first_vault.to_account_info(),
+            ctx.accounts.signer.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            amount_in,
+            first_mint.decimals,
+        )?;
+        for (index, (token_in, amount_in, amount_out)) in legs.into_iter().enumerate() {
+            let (_, (vault_out, mint_out)) = route_hop_sides(&hops[index], token_in);
+            let recipient = match hops.get(index + 1) {
+                Some(next) => {
+                    let ((next_vault, _), _) = route_hop_sides(next, mint_out.key());
+                    next_vault.to_account_info()
+                }
+                None => ctx
+                    .accounts
+                    .user_destination_token_account
+                    .to_account_info(),
+            };
+            let hop = &hops[index];
+            let mint_token1_key = hop.data_account.token_1_mint;
+            let mint_token2_key = hop.data_account.token_2_mint;
+            let seeds: &[&[&[u8]]] = &[&[
+                b"pool_authority",
+                mint_token1_key.as_ref(),
+                mint_token2_key.as_ref(),
+                &[hop.data_account.pool_authority_bump],
+            ]];
+            transfer_tokens_general_from_pool_to_user(
+                mint_out.to_account_info(),
+                vault_out.to_account_info(),
+                recipient,
+                hop.pool_authority.clone(),
                 ctx.accounts.token_program.to_account_info(),
-                amount_adding_to_pool,
-                ctx.accounts.mint_token2.decimals,
+                amount_out,
+                mint_out.decimals,
+                seeds,
             )?;
+            let token_out = mint_out.key();
+            let hop = &mut hops[index];
+            update_price_cumulative(&mut hop.data_account)?;
+            write_observation(&mut hop.observations, &hop.data_account);
+            let (fee, protocol_fee) = book_swap(
+                &mut hop.data_account,
+                token_in,
+                amount_in,
+                amount_out,
+                ctx.accounts.config.protocol_fee_bps,
+            )?;
+            hop.data_account.exit(&crate::ID)?;
+            hop.observations.exit(&crate::ID)?;
+            emit_cpi!(Swapped {
+                pool: hop.data_account.key(),
+                user: ctx.accounts.signer.key(),
+                token_in,
+                token_out,
+                amount_in,
+                amount_out,
+                fee,
+                protocol_fee,
+                token_1_balance: hop.data_account.token_1_balance,
+                token_2_balance: hop.data_account.token_2_balance,
+                lp_supply: hop.lp_mint.supply,
+            });
+        }
+        Ok(())
+    }
+
+    // lends amount of token straight out of the pool's vault, a flash_loan_repay for the same
+    // pool has to follow later in the transaction, every other pool instruction is blocked
+    // until it does
+    pub fn flash_loan_borrow(ctx: Context<FlashLoan>, token: Pubkey, amount: u64) -> Result<()> {
+        require!(
+            ctx.accounts.data_account.flash_loan_amount == 0,
+            GeneralErrors::FlashLoanActive
+        );
+        require!(amount > 0, GeneralErrors::InsufficientAmount);
+        // only the borrow is held back by a pause so an outstanding loan can always be repaid
+        require!(
+            ((ctx.accounts.config.paused | ctx.accounts.data_account.paused) & PAUSE_SWAPS) == 0,
+            GeneralErrors::Paused
+        );
+        require_keys_eq!(
+            ctx.accounts.borrower_token_account.mint,
+            token,
+            GeneralErrors::MintNotInPool
+        );
+        check_flash_loan_repaid(
+            &ctx.accounts.instructions.to_account_info(),
+            ctx.accounts.data_account.key(),
+        )?;
+        let mint_token1_key = ctx.accounts.mint_token1.key();
+        let mint_token2_key = ctx.accounts.mint_token2.key();
+        let seeds: &[&[&[u8]]] = &[&[
+            b"pool_authority",
+            mint_token1_key.as_ref(),
+            mint_token2_key.as_ref(),
+            &[ctx.accounts.data_account.pool_authority_bump],
+        ]];
+        if token == ctx.accounts.data_account.token_1_mint {
+            require!(
+                amount <= ctx.accounts.data_account.token_1_balance,
+                GeneralErrors::PoolInsufficient
+            );
             transfer_tokens_general_from_pool_to_user(
                 ctx.accounts.mint_token1.to_account_info(),
                 ctx.accounts.token_1_account.to_account_info(),
-                ctx.accounts.token_1_account_of_user.to_account_info(),
+                ctx.accounts.borrower_token_account.to_account_info(),
                 ctx.accounts.pool_authority.to_account_info(),
                 ctx.accounts.token_program.to_account_info(),
-                amount_to_send_to_user,
+                amount,
                 ctx.accounts.mint_token1.decimals,
                 seeds,
             )?;
-            ctx.accounts.data_account.token_2_balance += amount_adding_to_pool;
-            ctx.accounts.data_account.token_1_balance -= amount_to_send_to_user;
+        } else if token == ctx.accounts.data_account.token_2_mint {
+            require!(
+                amount <= ctx.accounts.data_account.token_2_balance,
+                GeneralErrors::PoolInsufficient
+            );
+            transfer_tokens_general_from_pool_to_user(
+                ctx.accounts.mint_token2.to_account_info(),
+                ctx.accounts.token_2_account.to_account_info(),
+                ctx.accounts.borrower_token_account.to_account_info(),
+                ctx.accounts.pool_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                amount,
+                ctx.accounts.mint_token2.decimals,
+                seeds,
+            )?;
+        } else {
+            return err!(GeneralErrors::MintNotInPool);
+        }
+        ctx.accounts.data_account.flash_loan_mint = token;
+        ctx.accounts.data_account.flash_loan_amount = amount;
+        ctx.accounts.data_account.flash_loan_fee = mul_div_ceil(
+            amount,
+            ctx.accounts.config.flash_loan_fee_bps as u64,
+            FEE_BPS_DENOMINATOR,
+        )?;
+        Ok(())
+    }
+
+    // pulls the outstanding flash loan plus its fee from the borrower token account,
+    // the fee stays in the reserves so it accrues to the LPs
+    pub fn flash_loan_repay(ctx: Context<FlashLoan>) -> Result<()> {
+        let token = ctx.accounts.data_account.flash_loan_mint;
+        let amount = ctx.accounts.data_account.flash_loan_amount;
+        let fee = ctx.accounts.data_account.flash_loan_fee;
+        require!(amount > 0, GeneralErrors::NoFlashLoan);
+        require_keys_eq!(
+            ctx.accounts.borrower_token_account.mint,
+            token,
+            GeneralErrors::MintNotInPool
+        );
+        let amount_owed = amount.checked_add(fee).ok_or(GeneralErrors::MathOverflow)?;
+        update_price_cumulative(&mut ctx.accounts.data_account)?;
+        if token == ctx.accounts.data_account.token_1_mint {
+            transfer_tokens_general_from_user_to_pool(
+                ctx.accounts.mint_token1.to_account_info(),
+                ctx.accounts.borrower_token_account.to_account_info(),
+                ctx.accounts.token_1_account.to_account_info(),
+                ctx.accounts.signer.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                amount_owed,
+                ctx.accounts.mint_token1.decimals,
+            )?;
+            ctx.accounts.data_account.token_1_balance = ctx
+                .accounts
+                .data_account
+                .token_1_balance
+                .checked_add(fee)
+                .ok_or(GeneralErrors::MathOverflow)?;
+        } else {
+            transfer_tokens_general_from_user_to_pool(
+                ctx.accounts.mint_token2.to_account_info(),
+                ctx.accounts.borrower_token_account.to_account_info(),
+                ctx.accounts.token_2_account.to_account_info(),
+                ctx.accounts.signer.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                amount_owed,
+                ctx.accounts.mint_token2.decimals,
+            )?;
+            ctx.accounts.data_account.token_2_balance = ctx
+                .accounts
+                .data_account
+                .token_2_balance
+                .checked_add(fee)
+                .ok_or(GeneralErrors::MathOverflow)?;
         }
+        ctx.accounts.data_account.flash_loan_mint = Pubkey::default();
+        ctx.accounts.data_account.flash_loan_amount = 0;
+        ctx.accounts.data_account.flash_loan_fee = 0;
+        emit_cpi!(FlashLoanRepaid {
+            pool: ctx.accounts.data_account.key(),
+            user: ctx.accounts.signer.key(),
+            token,
+            amount,
+            fee,
+            token_1_balance: ctx.accounts.data_account.token_1_balance,
+            token_2_balance: ctx.accounts.data_account.token_2_balance,
+        });
         Ok(())
     }
 
-    pub fn remove_liquidity(ctx: Context<RemoveLiquidity>, amount_of_lp: u64) -> Result<()> {
+    // min_token1_out and min_token2_out are the least the caller accepts back for the LP burnt
+    pub fn remove_liquidity(
+        ctx: Context<RemoveLiquidity>,
+        amount_of_lp: u64,
+        min_token1_out: u64,
+        min_token2_out: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
         let (token1_to_return, token2_to_return) = tokens_to_return_while_remove_liquidity(
             amount_of_lp,
             ctx.accounts.lp_mint.supply,
-            ctx.accounts.token_1_account.amount,
-            ctx.accounts.token_2_account.amount,
+            ctx.accounts.data_account.token_1_balance,
+            ctx.accounts.data_account.token_2_balance,
         )?;
+        require!(
+            token1_to_return >= min_token1_out && token2_to_return >= min_token2_out,
+            GeneralErrors::WithdrawalBelowMinimum
+        );
+        update_price_cumulative(&mut ctx.accounts.data_account)?;
         burn_lp_tokens_from_user(
             amount_of_lp,
             ctx.accounts.user_lp_ata.to_account_info(),
@@ -180,7 +1074,14 @@ pub mod amm {
             ctx.accounts.lp_mint.to_account_info(),
             ctx.accounts.lp_mint.decimals,
         )?;
-        let seeds: &[&[&[u8]]] = &[&[b"pool_authority", &[ctx.bumps.pool_authority]]];
+        let mint_token1_key = ctx.accounts.mint_token1.key();
+        let mint_token2_key = ctx.accounts.mint_token2.key();
+        let seeds: &[&[&[u8]]] = &[&[
+            b"pool_authority",
+            mint_token1_key.as_ref(),
+            mint_token2_key.as_ref(),
+            &[ctx.accounts.data_account.pool_authority_bump],
+        ]];
         transfer_tokens_general_from_pool_to_user(
             ctx.accounts.mint_token1.to_account_info(),
             ctx.accounts.token_1_account.to_account_info(),
@@ -203,10 +1104,558 @@ pub mod amm {
         )?;
         ctx.accounts.data_account.token_1_balance -= token1_to_return;
         ctx.accounts.data_account.token_2_balance -= token2_to_return;
+        emit_cpi!(LiquidityRemoved {
+            pool: ctx.accounts.data_account.key(),
+            user: ctx.accounts.signer.key(),
+            token_1_amount: token1_to_return,
+            token_2_amount: token2_to_return,
+            lp_burned: amount_of_lp,
+            token_1_balance: ctx.accounts.data_account.token_1_balance,
+            token_2_balance: ctx.accounts.data_account.token_2_balance,
+            lp_supply: ctx.accounts.lp_mint.supply - amount_of_lp,
+        });
         Ok(())
     }
+
+    // burns amount_of_lp for its share of both tokens and swaps the share of the other token
+    // into token_out against the reserves left after the withdrawal, paying out token_out only
+    // min_out is the least of token_out the caller accepts back
+    pub fn withdraw_single_token(
+        ctx: Context<WithdrawSingleToken>,
+        amount_of_lp: u64,
+        token_out: Pubkey,
+        min_out: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+        let token1_mint = ctx.accounts.data_account.token_1_mint;
+        let token2_mint = ctx.accounts.data_account.token_2_mint;
+        let is_token_1 = token_out == token1_mint;
+        require!(
+            is_token_1 || token_out == token2_mint,
+            GeneralErrors::MintNotInPool
+        );
+        // the burn only happens once the swap is priced, so the LP amount is bounded up front
+        let lp_supply_after = ctx
+            .accounts
+            .lp_mint
+            .supply
+            .checked_sub(amount_of_lp)
+            .ok_or(GeneralErrors::MathUnderflow)?;
+        let (token1_to_return, token2_to_return) = tokens_to_return_while_remove_liquidity(
+            amount_of_lp,
+            ctx.accounts.lp_mint.supply,
+            ctx.accounts.data_account.token_1_balance,
+            ctx.accounts.data_account.token_2_balance,
+        )?;
+        update_price_cumulative(&mut ctx.accounts.data_account)?;
+        ctx.accounts.data_account.token_1_balance = ctx
+            .accounts
+            .data_account
+            .token_1_balance
+            .checked_sub(token1_to_return)
+            .ok_or(GeneralErrors::MathUnderflow)?;
+        ctx.accounts.data_account.token_2_balance = ctx
+            .accounts
+            .data_account
+            .token_2_balance
+            .checked_sub(token2_to_return)
+            .ok_or(GeneralErrors::MathUnderflow)?;
+        let removed = LiquidityRemoved {
+            pool: ctx.accounts.data_account.key(),
+            user: ctx.accounts.signer.key(),
+            token_1_amount: token1_to_return,
+            token_2_amount: token2_to_return,
+            lp_burned: amount_of_lp,
+            token_1_balance: ctx.accounts.data_account.token_1_balance,
+            token_2_balance: ctx.accounts.data_account.token_2_balance,
+            lp_supply: lp_supply_after,
+        };
+        // the share of the other token never leaves the vault, it is sold to the pool in place
+        let (token_in, amount_in, amount_kept) = if is_token_1 {
+            (token2_mint, token2_to_return, token1_to_return)
+        } else {
+            (token1_mint, token1_to_return, token2_to_return)
+        };
+        let mut swapped = None;
+        let mut amount_out = amount_kept;
+        if amount_in > 0 {
+            let swap_out = get_swap_quote(
+                ctx.accounts.data_account.token_1_balance,
+                ctx.accounts.data_account.token_2_balance,
+                token1_mint,
+                after_fee(amount_in, ctx.accounts.data_account.fee_bps)?,
+                token_in,
+            )?;
+            write_observation(&mut ctx.accounts.observations, &ctx.accounts.data_account);
+            let (fee, protocol_fee) = book_swap(
+                &mut ctx.accounts.data_account,
+                token_in,
+                amount_in,
+                swap_out,
+                ctx.accounts.config.protocol_fee_bps,
+            )?;
+            swapped = Some(Swapped {
+                pool: ctx.accounts.data_account.key(),
+                user: ctx.accounts.signer.key(),
+                token_in,
+                token_out,
+                amount_in,
+                amount_out: swap_out,
+                fee,
+                protocol_fee,
+                token_1_balance: ctx.accounts.data_account.token_1_balance,
+                token_2_balance: ctx.accounts.data_account.token_2_balance,
+                lp_supply: lp_supply_after,
+            });
+            amount_out = amount_out
+                .checked_add(swap_out)
+                .ok_or(GeneralErrors::MathOverflow)?;
+        }
+        require!(amount_out >= min_out, GeneralErrors::WithdrawalBelowMinimum);
+        burn_lp_tokens_from_user(
+            amount_of_lp,
+            ctx.accounts.user_lp_ata.to_account_info(),
+            ctx.accounts.signer.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.lp_mint.to_account_info(),
+            ctx.accounts.lp_mint.decimals,
+        )?;
+        let mint_token1_key = ctx.accounts.mint_token1.key();
+        let mint_token2_key = ctx.accounts.mint_token2.key();
+        let seeds: &[&[&[u8]]] = &[&[
+            b"pool_authority",
+            mint_token1_key.as_ref(),
+            mint_token2_key.as_ref(),
+            &[ctx.accounts.data_account.pool_authority_bump],
+        ]];
+        let (mint, vault, user_account) = if is_token_1 {
+            (
+                &ctx.accounts.mint_token1,
+                &ctx.accounts.token_1_account,
+                &ctx.accounts.token_1_account_of_user,
+            )
+        } else {
+            (
+                &ctx.accounts.mint_token2,
+                &ctx.accounts.token_2_account,
+                &ctx.accounts.token_2_account_of_user,
+            )
+        };
+        transfer_tokens_general_from_pool_to_user(
+            mint.to_account_info(),
+            vault.to_account_info(),
+            user_account.to_account_info(),
+            ctx.accounts.pool_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            amount_out,
+            mint.decimals,
+            seeds,
+        )?;
+        emit_cpi!(removed);
+        if let Some(swapped) = swapped {
+            emit_cpi!(swapped);
+        }
+        Ok(())
+    }
+
+    // adopts whatever the vaults hold, minus accrued protocol fees, as the pool reserves
+    pub fn sync(ctx: Context<SyncPool>) -> Result<()> {
+        // before the first deposit there are no LPs to credit, syncing a donation then would
+        // leave reserves with no LP supply behind them
+        require!(ctx.accounts.lp_mint.supply > 0, GeneralErrors::PoolEmpty);
+        let (token1_in_vault, token2_in_vault) = vault_balances_owned_by_lps(
+            &ctx.accounts.data_account,
+            ctx.accounts.token_1_account.amount,
+            ctx.accounts.token_2_account.amount,
+        );
+        update_price_cumulative(&mut ctx.accounts.data_account)?;
+        ctx.accounts.data_account.token_1_balance = token1_in_vault;
+        ctx.accounts.data_account.token_2_balance = token2_in_vault;
+        Ok(())
+    }
+
+    // sends anything the vaults hold above the reserves and accrued protocol fees to the
+    // recipient token accounts
+    pub fn skim(ctx: Context<SkimPool>) -> Result<()> {
+        let (token1_in_vault, token2_in_vault) = vault_balances_owned_by_lps(
+            &ctx.accounts.data_account,
+            ctx.accounts.token_1_account.amount,
+            ctx.accounts.token_2_account.amount,
+        );
+        let token1_excess =
+            token1_in_vault.saturating_sub(ctx.accounts.data_account.token_1_balance);
+        let token2_excess =
+            token2_in_vault.saturating_sub(ctx.accounts.data_account.token_2_balance);
+        let mint_token1_key = ctx.accounts.mint_token1.key();
+        let mint_token2_key = ctx.accounts.mint_token2.key();
+        let seeds: &[&[&[u8]]] = &[&[
+            b"pool_authority",
+            mint_token1_key.as_ref(),
+            mint_token2_key.as_ref(),
+            &[ctx.accounts.data_account.pool_authority_bump],
+        ]];
+        if token1_excess > 0 {
+            transfer_tokens_general_from_pool_to_user(
+                ctx.accounts.mint_token1.to_account_info(),
+                ctx.accounts.token_1_account.to_account_info(),
+                ctx.accounts.recipient_token_1_account.to_account_info(),
+                ctx.accounts.pool_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                token1_excess,
+                ctx.accounts.mint_token1.decimals,
+                seeds,
+            )?;
+        }
+        if token2_excess > 0 {
+            transfer_tokens_general_from_pool_to_user(
+                ctx.accounts.mint_token2.to_account_info(),
+                ctx.accounts.token_2_account.to_account_info(),
+                ctx.accounts.recipient_token_2_account.to_account_info(),
+                ctx.accounts.pool_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                token2_excess,
+                ctx.accounts.mint_token2.decimals,
+                seeds,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// a pool's mints in the canonical ascending order its seeds and token_1/token_2 fields use
+pub fn sort_mints(mint_a: Pubkey, mint_b: Pubkey) -> (Pubkey, Pubkey) {
+    (mint_a.min(mint_b), mint_a.max(mint_b))
+}
+
+// pulls amount_in of token_in from the user, pays amount_out of the other token back
+// and books both legs against the tracked pool balances, returns the event describing
+// the trade
+pub fn settle_swap(
+    accounts: &mut SwapToken,
+    token_in: Pubkey,
+    amount_in: u64,
+    amount_out: u64,
+) -> Result<Swapped> {
+    update_price_cumulative(&mut accounts.data_account)?;
+    write_observation(&mut accounts.observations, &accounts.data_account);
+    let (fee, protocol_fee) = book_swap(
+        &mut accounts.data_account,
+        token_in,
+        amount_in,
+        amount_out,
+        accounts.config.protocol_fee_bps,
+    )?;
+    let mint_token1_key = accounts.mint_token1.key();
+    let mint_token2_key = accounts.mint_token2.key();
+    let seeds: &[&[&[u8]]] = &[&[
+        b"pool_authority",
+        mint_token1_key.as_ref(),
+        mint_token2_key.as_ref(),
+        &[accounts.data_account.pool_authority_bump],
+    ]];
+    if token_in == accounts.data_account.token_1_mint {
+        transfer_tokens_general_from_user_to_pool(
+            accounts.mint_token1.to_account_info(),
+            accounts.token_1_account_of_user.to_account_info(),
+            accounts.token_1_account.to_account_info(),
+            accounts.signer.to_account_info(),
+            accounts.token_program.to_account_info(),
+            amount_in,
+            accounts.mint_token1.decimals,
+        )?;
+        transfer_tokens_general_from_pool_to_user(
+            accounts.mint_token2.to_account_info(),
+            accounts.token_2_account.to_account_info(),
+            accounts.token_2_account_of_user.to_account_info(),
+            accounts.pool_authority.to_account_info(),
+            accounts.token_program.to_account_info(),
+            amount_out,
+            accounts.mint_token2.decimals,
+            seeds,
+        )?;
+    } else {
+        transfer_tokens_general_from_user_to_pool(
+            accounts.mint_token2.to_account_info(),
+            accounts.token_2_account_of_user.to_account_info(),
+            accounts.token_2_account.to_account_info(),
+            accounts.signer.to_account_info(),
+            accounts.token_program.to_account_info(),
+            amount_in,
+            accounts.mint_token2.decimals,
+        )?;
+        transfer_tokens_general_from_pool_to_user(
+            accounts.mint_token1.to_account_info(),
+            accounts.token_1_account.to_account_info(),
+            accounts.token_1_account_of_user.to_account_info(),
+            accounts.pool_authority.to_account_info(),
+            accounts.token_program.to_account_info(),
+            amount_out,
+            accounts.mint_token1.decimals,
+            seeds,
+        )?;
+    }
+    let token_out = if token_in == accounts.data_account.token_1_mint {
+        accounts.data_account.token_2_mint
+    } else {
+        accounts.data_account.token_1_mint
+    };
+    Ok(Swapped {
+        pool: accounts.data_account.key(),
+        user: accounts.signer.key(),
+        token_in,
+        token_out,
+        amount_in,
+        amount_out,
+        fee,
+        protocol_fee,
+        token_1_balance: accounts.data_account.token_1_balance,
+        token_2_balance: accounts.data_account.token_2_balance,
+        lp_supply: accounts.lp_mint.supply,
+    })
+}
+
+// hands control to the flash swap callback, pool_authority never signs here so the
+// callback can pay tokens into the vaults but never move them out
+pub fn invoke_flash_callback<'info>(
+    callback_program: AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+) -> Result<()> {
+    let instruction = Instruction {
+        program_id: callback_program.key(),
+        accounts: accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data,
+    };
+    let mut account_infos = accounts.to_vec();
+    account_infos.push(callback_program);
+    invoke(&instruction, &account_infos)?;
+    Ok(())
+}
+
+// whatever the vaults gained over the reserves left after the borrow counts as paid in,
+// the swap fee is charged on those inputs and the reserves net of it must keep at least
+// the product they started with, returns (token1 in, token2 in, token1 fee, token2 fee)
+pub fn check_flash_swap_invariant(
+    token1_reserve: u64,
+    token2_reserve: u64,
+    token1_amount_out: u64,
+    token2_amount_out: u64,
+    token1_balance: u64,
+    token2_balance: u64,
+    fee_bps: u16,
+) -> Result<(u64, u64, u64, u64)> {
+    let token1_in = token1_balance.saturating_sub(token1_reserve - token1_amount_out);
+    let token2_in = token2_balance.saturating_sub(token2_reserve - token2_amount_out);
+    let token1_fee = mul_div_ceil(token1_in, fee_bps as u64, FEE_BPS_DENOMINATOR)?;
+    let token2_fee = mul_div_ceil(token2_in, fee_bps as u64, FEE_BPS_DENOMINATOR)?;
+    let token1_adjusted = (token1_balance - token1_fee) as u128;
+    let token2_adjusted = (token2_balance - token2_fee) as u128;
+    require!(
+        token1_adjusted * token2_adjusted >= token1_reserve as u128 * token2_reserve as u128,
+        GeneralErrors::InvariantViolated
+    );
+    Ok((token1_in, token2_in, token1_fee, token2_fee))
+}
+
+// a borrow has to be a top level instruction followed by a flash_loan_repay of the same pool,
+// the repay then runs unless the whole transaction fails
+pub fn check_flash_loan_repaid(instructions: &AccountInfo, data_account: Pubkey) -> Result<()> {
+    let current = get_instruction_relative(0, instructions)?;
+    require_keys_eq!(
+        current.program_id,
+        crate::ID,
+        GeneralErrors::FlashLoanFromCpi
+    );
+    let mut index = load_current_index_checked(instructions)? as usize + 1;
+    while let Ok(next) = load_instruction_at_checked(index, instructions) {
+        if next.program_id == crate::ID
+            && next
+                .data
+                .starts_with(instruction::FlashLoanRepay::DISCRIMINATOR)
+            && next.accounts.get(1).map(|account| account.pubkey) == Some(data_account)
+        {
+            return Ok(());
+        }
+        index += 1;
+    }
+    err!(GeneralErrors::FlashLoanNotRepaid)
+}
+
+// books amount_in of token_in and amount_out of the other token against the tracked pool
+// balances, the protocol share of the fee is kept out of the reserves,
+// returns (fee, protocol_fee)
+pub fn book_swap(
+    data_account: &mut DataAccount,
+    token_in: Pubkey,
+    amount_in: u64,
+    amount_out: u64,
+    protocol_fee_bps: u16,
+) -> Result<(u64, u64)> {
+    let fee = amount_in
+        .checked_sub(after_fee(amount_in, data_account.fee_bps)?)
+        .ok_or(GeneralErrors::MathUnderflow)?;
+    let protocol_fee = protocol_fee_share(fee, protocol_fee_bps)?;
+    let amount_to_reserves = amount_in - protocol_fee;
+    let (balance_in, balance_out, protocol_fees) = if token_in == data_account.token_1_mint {
+        (
+            &mut data_account.token_1_balance,
+            &mut data_account.token_2_balance,
+            &mut data_account.protocol_fees_token_1,
+        )
+    } else {
+        (
+            &mut data_account.token_2_balance,
+            &mut data_account.token_1_balance,
+            &mut data_account.protocol_fees_token_2,
+        )
+    };
+    *balance_in = balance_in
+        .checked_add(amount_to_reserves)
+        .ok_or(GeneralErrors::MathOverflow)?;
+    *balance_out = balance_out
+        .checked_sub(amount_out)
+        .ok_or(GeneralErrors::MathUnderflow)?;
+    *protocol_fees = protocol_fees
+        .checked_add(protocol_fee)
+        .ok_or(GeneralErrors::MathOverflow)?;
+    Ok((fee, protocol_fee))
+}
+
+// checks every hop's accounts the way the SwapToken constraints would, a pool may only
+// appear once since each hop works on its own copy of the pool state
+pub fn load_route_hops<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    token_program: &Pubkey,
+) -> Result<Vec<RouteHop<'info>>> {
+    let chunks = accounts.chunks_exact(ROUTE_HOP_ACCOUNTS);
+    require!(
+        chunks.len() > 0 && chunks.remainder().is_empty(),
+        GeneralErrors::InvalidRoute
+    );
+    let mut hops: Vec<RouteHop<'info>> = Vec::with_capacity(chunks.len());
+    for hop_accounts in chunks {
+        let data_account = Account::<DataAccount>::try_from(&hop_accounts[0])?;
+        require!(
+            data_account.flash_loan_amount == 0,
+            GeneralErrors::FlashLoanActive
+        );
+        require!(
+            (data_account.paused & PAUSE_SWAPS) == 0,
+            GeneralErrors::Paused
+        );
+        require!(
+            hops.iter()
+                .all(|hop| hop.data_account.key() != data_account.key()),
+            GeneralErrors::InvalidRoute
+        );
+        let observations = Account::<Observations>::try_from(&hop_accounts[1])?;
+        require_keys_eq!(
+            observations.pool,
+            data_account.key(),
+            GeneralErrors::InvalidRoute
+        );
+        let mint_token1_key = data_account.token_1_mint;
+        let mint_token2_key = data_account.token_2_mint;
+        let pool_authority = Pubkey::create_program_address(
+            &[
+                b"pool_authority",
+                mint_token1_key.as_ref(),
+                mint_token2_key.as_ref(),
+                &[data_account.pool_authority_bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| GeneralErrors::InvalidRoute)?;
+        let lp_mint = Pubkey::create_program_address(
+            &[
+                b"mint",
+                mint_token1_key.as_ref(),
+                mint_token2_key.as_ref(),
+                &[data_account.lp_mint_bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| GeneralErrors::InvalidRoute)?;
+        for (account, expected) in [
+            (&hop_accounts[2], pool_authority),
+            (&hop_accounts[3], lp_mint),
+            (
+                &hop_accounts[4],
+                get_associated_token_address_with_program_id(
+                    &pool_authority,
+                    &mint_token1_key,
+                    token_program,
+                ),
+            ),
+            (
+                &hop_accounts[5],
+                get_associated_token_address_with_program_id(
+                    &pool_authority,
+                    &mint_token2_key,
+                    token_program,
+                ),
+            ),
+            (&hop_accounts[6], mint_token1_key),
+            (&hop_accounts[7], mint_token2_key),
+        ] {
+            require_keys_eq!(account.key(), expected, GeneralErrors::InvalidRoute);
+        }
+        hops.push(RouteHop {
+            data_account,
+            observations,
+            pool_authority: hop_accounts[2].clone(),
+            lp_mint: InterfaceAccount::try_from(&hop_accounts[3])?,
+            token_1_account: InterfaceAccount::try_from(&hop_accounts[4])?,
+            token_2_account: InterfaceAccount::try_from(&hop_accounts[5])?,
+            mint_token1: InterfaceAccount::try_from(&hop_accounts[6])?,
+            mint_token2: InterfaceAccount::try_from(&hop_accounts[7])?,
+        });
+    }
+    Ok(hops)
+}
+
+// a hop's vault and mint for one of its two tokens
+pub type RouteHopSide<'a, 'info> = (
+    &'a InterfaceAccount<'info, TokenAccount>,
+    &'a InterfaceAccount<'info, Mint>,
+);
+
+// the side a hop takes token_in on and the side it pays the other token out of
+pub fn route_hop_sides<'a, 'info>(
+    hop: &'a RouteHop<'info>,
+    token_in: Pubkey,
+) -> (RouteHopSide<'a, 'info>, RouteHopSide<'a, 'info>) {
+    let token_1_side = (&hop.token_1_account, &hop.mint_token1);
+    let token_2_side = (&hop.token_2_account, &hop.mint_token2);
+    if token_in == hop.data_account.token_1_mint {
+        (token_1_side, token_2_side)
+    } else {
+        (token_2_side, token_1_side)
+    }
+}
+
+// DataAccount balances are the pool reserves every instruction prices against, the vaults
+// can only differ from them by accrued protocol fees plus anything sent in directly
+pub fn vault_balances_owned_by_lps(
+    data_account: &DataAccount,
+    token_1_vault_amount: u64,
+    token_2_vault_amount: u64,
+) -> (u64, u64) {
+    (
+        token_1_vault_amount.saturating_sub(data_account.protocol_fees_token_1),
+        token_2_vault_amount.saturating_sub(data_account.protocol_fees_token_2),
+    )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn transfer_tokens_general_from_pool_to_user<'info>(
     mint_account: AccountInfo<'info>,
     from: AccountInfo<'info>,
@@ -266,14 +1715,67 @@ pub fn mint_lp_tokens<'info>(
     Ok(())
 }
 
-pub fn calc_first_lp_mint(token1_amount: u64, token2_amount: u64) -> u64 {
-    let product = token1_amount
-        .checked_mul(token2_amount)
-        .expect("Overflow in multiplication");
-    integer_sqrt(product)
+// sizes a deposit of at most max_token1_amount and max_token2_amount against the pool,
+// returns (token1 to deposit, token2 to deposit, LP for the depositor, LP to lock)
+pub fn calc_add_liquidity(
+    max_token1_amount: u64,
+    max_token2_amount: u64,
+    token1_reserve: u64,
+    token2_reserve: u64,
+    lp_supply: u64,
+) -> Result<(u64, u64, u64, u64)> {
+    if lp_supply == 0 {
+        // no LP owns the reserves yet, anything already in the pool joins the first deposit
+        let token1_total = token1_reserve
+            .checked_add(max_token1_amount)
+            .ok_or(GeneralErrors::MathOverflow)?;
+        let token2_total = token2_reserve
+            .checked_add(max_token2_amount)
+            .ok_or(GeneralErrors::MathOverflow)?;
+        let amount_to_mint = calc_first_lp_mint(token1_total, token2_total)?
+            .checked_sub(MINIMUM_LIQUIDITY)
+            .filter(|amount| *amount > 0)
+            .ok_or(GeneralErrors::InitialLiquidityTooLow)?;
+        return Ok((
+            max_token1_amount,
+            max_token2_amount,
+            amount_to_mint,
+            MINIMUM_LIQUIDITY,
+        ));
+    }
+    require!(
+        token1_reserve > 0 && token2_reserve > 0,
+        GeneralErrors::PoolEmpty
+    );
+    let token2_for_all_token1 = mul_div_floor(max_token1_amount, token2_reserve, token1_reserve)?;
+    let (token1_amount, token2_amount) = if token2_for_all_token1 <= max_token2_amount {
+        (max_token1_amount, token2_for_all_token1)
+    } else {
+        // token2 binds, so the matching token1 is below max_token1_amount
+        let token1_for_all_token2 =
+            mul_div_floor(max_token2_amount, token1_reserve, token2_reserve)?;
+        (token1_for_all_token2, max_token2_amount)
+    };
+    let amount_to_mint = calc_subsequent_lp_mint(
+        token1_amount,
+        token2_amount,
+        token1_reserve,
+        token2_reserve,
+        lp_supply,
+    )?;
+    Ok((token1_amount, token2_amount, amount_to_mint, 0))
+}
+
+pub fn calc_first_lp_mint(token1_amount: u64, token2_amount: u64) -> Result<u64> {
+    // the product of two u64 always fits in u128 and its root always fits back in u64
+    let product = token1_amount as u128 * token2_amount as u128;
+    let lp_to_mint = integer_sqrt(product) as u64;
+    require!(lp_to_mint > 0, GeneralErrors::InsufficientLiquidityMinted);
+    Ok(lp_to_mint)
 }
 
-pub fn integer_sqrt(n: u64) -> u64 {
+// rounds down
+pub fn integer_sqrt(n: u128) -> u128 {
     if n == 0 {
         return 0;
     }
@@ -286,75 +1788,303 @@ pub fn integer_sqrt(n: u64) -> u64 {
     x0
 }
 
+// how much of a single sided deposit of amount to swap so the rest and the swap output
+// match the pool ratio afterwards, the positive root of g*s^2 + (1+g)*reserve*s - amount*reserve
+// with g the share of the input left after the fee, rounded down
+pub fn calc_single_sided_swap(amount: u64, reserve_in: u64, fee_bps: u16) -> Result<u64> {
+    let denominator = FEE_BPS_DENOMINATOR as u128;
+    let g = denominator - fee_bps as u128;
+    let h = denominator + g;
+    let reserve = reserve_in as u128;
+    // scaled by the bps denominator the discriminant is
+    // reserve * (reserve * h^2 + 4 * g * denominator * amount), which needs 256 bits
+    let root = sqrt_of_product(
+        reserve,
+        reserve * h * h + 4 * g * denominator * amount as u128,
+    );
+    let swap_amount = (root - reserve * h) / (2 * g);
+    u64::try_from(swap_amount).map_err(|_| GeneralErrors::MathOverflow.into())
+}
+
+// rounds down, for products too wide for u128 it searches between the roots of the factors
+// multiplied together, rounded down and rounded up
+pub fn sqrt_of_product(x: u128, y: u128) -> u128 {
+    let product = widening_mul(x, y);
+    let mut low = integer_sqrt(x).saturating_mul(integer_sqrt(y));
+    let mut high = (integer_sqrt(x) + 1).saturating_mul(integer_sqrt(y) + 1);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if widening_mul(mid, mid) <= product {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+// x * y as (high, low) u128 halves of the 256 bit product
+pub fn widening_mul(x: u128, y: u128) -> (u128, u128) {
+    let mask = u64::MAX as u128;
+    let (x_high, x_low) = (x >> 64, x & mask);
+    let (y_high, y_low) = (y >> 64, y & mask);
+    let low_low = x_low * y_low;
+    let high_low = x_high * y_low;
+    let low_high = x_low * y_high;
+    let middle = (low_low >> 64) + (high_low & mask) + (low_high & mask);
+    let low = (middle << 64) | (low_low & mask);
+    let high = x_high * y_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+    (high, low)
+}
+
+// rounds down so depositors never get more LP than their share
 pub fn calc_subsequent_lp_mint(
     new_token1_amount: u64,
     new_token2_amount: u64,
     old_token1_reserve: u64,
     old_token2_reserve: u64,
     old_total_lp_supply: u64,
-) -> u64 {
-    assert!(old_token1_reserve > 0 && old_token2_reserve > 0 && old_total_lp_supply > 0);
-    let lp_from_token1 = new_token1_amount
-        .checked_mul(old_total_lp_supply)
-        .expect("Overflow in token1 LP calc")
-        / old_token1_reserve;
+) -> Result<u64> {
+    require!(
+        old_token1_reserve > 0 && old_token2_reserve > 0 && old_total_lp_supply > 0,
+        GeneralErrors::PoolEmpty
+    );
+    let lp_from_token1 = mul_div_floor(new_token1_amount, old_total_lp_supply, old_token1_reserve)?;
     // Calculate LP from token2 side
-    let lp_from_token2 = new_token2_amount
-        .checked_mul(old_total_lp_supply)
-        .expect("Overflow in token2 LP calc")
-        / old_token2_reserve;
-    lp_from_token1.min(lp_from_token2)
-}
-
-pub fn after_fee(amount: u64) -> Result<u64> {
-    let fee_numerator: u64 = 3;
-    let fee_denominator: u64 = 1000;
-    let fee_amount = amount
-        .checked_mul(fee_numerator)
-        .ok_or(GeneralErrors::MathOverflow)?
-        .checked_div(fee_denominator)
-        .ok_or(GeneralErrors::MathDivisionByZero)?;
+    let lp_from_token2 = mul_div_floor(new_token2_amount, old_total_lp_supply, old_token2_reserve)?;
+    let lp_to_mint = lp_from_token1.min(lp_from_token2);
+    require!(lp_to_mint > 0, GeneralErrors::InsufficientLiquidityMinted);
+    Ok(lp_to_mint)
+}
+
+// a * b / c on a u128 intermediate, rounded down
+pub fn mul_div_floor(a: u64, b: u64, c: u64) -> Result<u64> {
+    require!(c > 0, GeneralErrors::MathDivisionByZero);
+    let result = a as u128 * b as u128 / c as u128;
+    u64::try_from(result).map_err(|_| GeneralErrors::MathOverflow.into())
+}
+
+// a * b / c on a u128 intermediate, rounded up
+pub fn mul_div_ceil(a: u64, b: u64, c: u64) -> Result<u64> {
+    require!(c > 0, GeneralErrors::MathDivisionByZero);
+    let result = (a as u128 * b as u128).div_ceil(c as u128);
+    u64::try_from(result).map_err(|_| GeneralErrors::MathOverflow.into())
+}
+
+// adds the price that held since the last update, weighted by the seconds it held for,
+// has to run before the reserves move so each second is charged at the price in effect
+pub fn update_price_cumulative(data_account: &mut DataAccount) -> Result<()> {
+    let current = current_observation(data_account, Clock::get()?.unix_timestamp)?;
+    data_account.price_1_cumulative = current.price_1_cumulative;
+    data_account.price_2_cumulative = current.price_2_cumulative;
+    data_account.last_price_update = current.timestamp;
+    Ok(())
+}
+
+// stores the pool's cumulative prices as of its last update, at most one sample per second
+pub fn write_observation(observations: &mut Observations, data_account: &DataAccount) {
+    let timestamp = data_account.last_price_update;
+    if let Some(latest) = observations.observations.get(observations.index as usize) {
+        if latest.timestamp >= timestamp {
+            return;
+        }
+    }
+    let observation = Observation {
+        timestamp,
+        price_1_cumulative: data_account.price_1_cumulative,
+        price_2_cumulative: data_account.price_2_cumulative,
+    };
+    if observations.observations.len() < observations.capacity as usize {
+        observations.observations.push(observation);
+        observations.index = (observations.observations.len() - 1) as u16;
+    } else {
+        observations.index = (observations.index + 1) % observations.capacity;
+        observations.observations[observations.index as usize] = observation;
+    }
+}
+
+// cumulative prices as they would read at `timestamp`, interpolated between the samples
+// around it or extrapolated from the pool's current reserves past the latest sample
+pub fn cumulative_prices_at(
+    data_account: &DataAccount,
+    observations: &Observations,
+    now: i64,
+    timestamp: i64,
+) -> Result<(u128, u128)> {
+    let samples = &observations.observations;
+    require!(!samples.is_empty(), GeneralErrors::NotEnoughObservations);
+    let latest = samples[observations.index as usize];
+    let (mut before, after) = if timestamp >= latest.timestamp {
+        (latest, current_observation(data_account, now)?)
+    } else {
+        // oldest sample sits right after the latest one once the buffer has wrapped
+        let oldest = (observations.index as usize + 1) % samples.len();
+        let mut previous = samples[oldest];
+        require!(
+            timestamp >= previous.timestamp,
+            GeneralErrors::NotEnoughObservations
+        );
+        let mut next = previous;
+        for i in 1..samples.len() {
+            next = samples[(oldest + i) % samples.len()];
+            if next.timestamp >= timestamp {
+                break;
+            }
+            previous = next;
+        }
+        (previous, next)
+    };
+    let span = after.timestamp - before.timestamp;
+    if span > 0 {
+        let offset = (timestamp - before.timestamp) as u128;
+        let price_1 = after
+            .price_1_cumulative
+            .wrapping_sub(before.price_1_cumulative)
+            / span as u128;
+        let price_2 = after
+            .price_2_cumulative
+            .wrapping_sub(before.price_2_cumulative)
+            / span as u128;
+        before.price_1_cumulative = before
+            .price_1_cumulative
+            .wrapping_add(price_1.wrapping_mul(offset));
+        before.price_2_cumulative = before
+            .price_2_cumulative
+            .wrapping_add(price_2.wrapping_mul(offset));
+    }
+    Ok((before.price_1_cumulative, before.price_2_cumulative))
+}
+
+// what write_observation would store if the pool were updated right now
+pub fn current_observation(data_account: &DataAccount, now: i64) -> Result<Observation> {
+    let mut price_1_cumulative = data_account.price_1_cumulative;
+    let mut price_2_cumulative = data_account.price_2_cumulative;
+    let elapsed = now.saturating_sub(data_account.last_price_update);
+    if elapsed > 0 && data_account.token_1_balance > 0 && data_account.token_2_balance > 0 {
+        let price_1 = q64_div(data_account.token_2_balance, data_account.token_1_balance)?;
+        let price_2 = q64_div(data_account.token_1_balance, data_account.token_2_balance)?;
+        price_1_cumulative = price_1_cumulative.wrapping_add(price_1.wrapping_mul(elapsed as u128));
+        price_2_cumulative = price_2_cumulative.wrapping_add(price_2.wrapping_mul(elapsed as u128));
+    }
+    Ok(Observation {
+        timestamp: data_account.last_price_update.max(now),
+        price_1_cumulative,
+        price_2_cumulative,
+    })
+}
+
+// time weighted average prices over the window_seconds leading up to now
+pub fn calc_twap(
+    data_account: &DataAccount,
+    observations: &Observations,
+    now: i64,
+    window_seconds: u32,
+) -> Result<(u128, u128)> {
+    require!(window_seconds > 0, GeneralErrors::InsufficientAmount);
+    let current = current_observation(data_account, now)?;
+    let (price_1_then, price_2_then) =
+        cumulative_prices_at(data_account, observations, now, now - window_seconds as i64)?;
+    let price_1 = current.price_1_cumulative.wrapping_sub(price_1_then) / window_seconds as u128;
+    let price_2 = current.price_2_cumulative.wrapping_sub(price_2_then) / window_seconds as u128;
+    Ok((price_1, price_2))
+}
+
+// numerator / denominator as a Q64.64 fixed point number, rounded down
+pub fn q64_div(numerator: u64, denominator: u64) -> Result<u128> {
+    require!(denominator > 0, GeneralErrors::MathDivisionByZero);
+    Ok(((numerator as u128) << 64) / denominator as u128)
+}
+
+// the fee is rounded up so it always favours the pool
+pub fn after_fee(amount: u64, fee_bps: u16) -> Result<u64> {
+    let fee_amount = mul_div_ceil(amount, fee_bps as u64, FEE_BPS_DENOMINATOR)?;
     let amount_after_fee = amount
         .checked_sub(fee_amount)
         .ok_or(GeneralErrors::MathUnderflow)?;
     Ok(amount_after_fee)
 }
 
-pub fn get_quote(
+// deadline is a unix timestamp checked against the cluster clock,
+// None means the caller doesn't want one
+pub fn check_deadline(deadline: Option<i64>) -> Result<()> {
+    if let Some(deadline) = deadline {
+        require!(
+            Clock::get()?.unix_timestamp <= deadline,
+            GeneralErrors::Expired
+        );
+    }
+    Ok(())
+}
+
+// rounds down, the remainder stays with the LPs
+pub fn protocol_fee_share(fee: u64, protocol_fee_bps: u16) -> Result<u64> {
+    mul_div_floor(fee, protocol_fee_bps as u64, FEE_BPS_DENOMINATOR)
+}
+
+// smallest amount whose after_fee value still covers amount_after_fee,
+// after_fee(x) is floor(x * (1 - fee)) so the ceiling of the inverse is exact
+pub fn before_fee(amount_after_fee: u64, fee_bps: u16) -> Result<u64> {
+    let kept_numerator = FEE_BPS_DENOMINATOR - fee_bps as u64;
+    mul_div_ceil(amount_after_fee, FEE_BPS_DENOMINATOR, kept_numerator)
+}
+
+// quote for selling amount_to_put_into_the_pool, with everything a front-end needs to show it
+pub fn get_quote_details(
     token1_balance: u64,
     token2_balance: u64,
     token1_mint: Pubkey,
     amount_to_put_into_the_pool: u64,
     token_to_put_into_the_pool: Pubkey,
-) -> Result<u64> {
-    let amount_after_fees = after_fee(amount_to_put_into_the_pool)?;
-    let k = token1_balance
-        .checked_mul(token2_balance)
-        .ok_or(GeneralErrors::MathOverflow)?;
-    let res: u64;
-    let tokens_to_remove_from_pool: u64;
-    if token_to_put_into_the_pool == token1_mint {
-        let new_t1_balance = token1_balance
-            .checked_add(amount_after_fees)
-            .ok_or(GeneralErrors::MathOverflow)?;
-        res = k
-            .checked_div(new_t1_balance)
-            .ok_or(GeneralErrors::MathDivisionByZero)?;
-        require!(token2_balance >= res, GeneralErrors::PoolInsufficient);
-        tokens_to_remove_from_pool = token2_balance - res;
+    fee_bps: u16,
+    protocol_fee_bps: u16,
+) -> Result<SwapQuote> {
+    require!(
+        amount_to_put_into_the_pool > 0,
+        GeneralErrors::InsufficientAmount
+    );
+    let amount_after_fees = after_fee(amount_to_put_into_the_pool, fee_bps)?;
+    let fee = amount_to_put_into_the_pool - amount_after_fees;
+    let protocol_fee = protocol_fee_share(fee, protocol_fee_bps)?;
+    let amount_out = get_swap_quote(
+        token1_balance,
+        token2_balance,
+        token1_mint,
+        amount_after_fees,
+        token_to_put_into_the_pool,
+    )?;
+    let (balance_in, balance_out) = if token_to_put_into_the_pool == token1_mint {
+        (token1_balance, token2_balance)
     } else {
-        let denom = token2_balance
-            .checked_add(amount_after_fees)
-            .ok_or(GeneralErrors::MathUnderflow)?;
-        res = k
-            .checked_div(denom)
-            .ok_or(GeneralErrors::MathDivisionByZero)?;
-        require!(res <= token1_balance, GeneralErrors::PoolInsufficient);
-        tokens_to_remove_from_pool = token1_balance - res;
-    }
-    Ok(tokens_to_remove_from_pool)
+        (token2_balance, token1_balance)
+    };
+    // what amount_after_fees would buy at the spot price, u128 as it can exceed the reserves
+    let amount_out_at_spot = amount_after_fees as u128 * balance_out as u128 / balance_in as u128;
+    let price_impact_bps = (amount_out_at_spot - amount_out as u128)
+        .checked_mul(FEE_BPS_DENOMINATOR as u128)
+        .ok_or(GeneralErrors::MathOverflow)?
+        .checked_div(amount_out_at_spot)
+        .unwrap_or(0) as u64;
+    let balance_in_after = balance_in
+        .checked_add(amount_to_put_into_the_pool - protocol_fee)
+        .ok_or(GeneralErrors::MathOverflow)?;
+    let balance_out_after = balance_out - amount_out;
+    let (token_1_balance_after, token_2_balance_after) =
+        if token_to_put_into_the_pool == token1_mint {
+            (balance_in_after, balance_out_after)
+        } else {
+            (balance_out_after, balance_in_after)
+        };
+    Ok(SwapQuote {
+        amount_out,
+        fee,
+        effective_price: q64_div(amount_out, amount_to_put_into_the_pool)?,
+        price_impact_bps,
+        token_1_balance_after,
+        token_2_balance_after,
+    })
 }
 
+// out = balance_out * in / (balance_in + in), rounded down so k never decreases
 pub fn get_swap_quote(
     token1_balance: u64,
     token2_balance: u64,
@@ -362,49 +2092,74 @@ pub fn get_swap_quote(
     amount_to_put_into_the_pool: u64,
     token_to_put_into_the_pool: Pubkey,
 ) -> Result<u64> {
-    let k = token1_balance
-        .checked_mul(token2_balance)
-        .ok_or(GeneralErrors::MathOverflow)?;
-    let res: u64;
-    let tokens_to_remove_from_pool: u64;
-    if token_to_put_into_the_pool == token1_mint {
-        let new_t1_balance = token1_balance
-            .checked_add(amount_to_put_into_the_pool)
-            .ok_or(GeneralErrors::MathOverflow)?;
-        res = k
-            .checked_div(new_t1_balance)
-            .ok_or(GeneralErrors::MathDivisionByZero)?;
-        require!(token2_balance >= res, GeneralErrors::PoolInsufficient);
-        tokens_to_remove_from_pool = token2_balance - res;
+    let (balance_in, balance_out) = if token_to_put_into_the_pool == token1_mint {
+        (token1_balance, token2_balance)
     } else {
-        let denom = token2_balance
-            .checked_add(amount_to_put_into_the_pool)
-            .ok_or(GeneralErrors::MathUnderflow)?;
-        res = k
-            .checked_div(denom)
-            .ok_or(GeneralErrors::MathDivisionByZero)?;
-        require!(res <= token1_balance, GeneralErrors::PoolInsufficient);
-        tokens_to_remove_from_pool = token1_balance - res;
-    }
+        (token2_balance, token1_balance)
+    };
+    let new_balance_in = balance_in
+        .checked_add(amount_to_put_into_the_pool)
+        .ok_or(GeneralErrors::MathOverflow)?;
+    let tokens_to_remove_from_pool =
+        mul_div_floor(balance_out, amount_to_put_into_the_pool, new_balance_in)?;
+    require!(
+        tokens_to_remove_from_pool < balance_out,
+        GeneralErrors::PoolInsufficient
+    );
     Ok(tokens_to_remove_from_pool)
 }
 
+// token means token you want to take out of the pool
+// amount is how much of it you want, the result is what has to go in including fees
+pub fn get_quote_exact_out(
+    token1_balance: u64,
+    token2_balance: u64,
+    token1_mint: Pubkey,
+    amount_to_take_from_the_pool: u64,
+    token_to_take_from_the_pool: Pubkey,
+    fee_bps: u16,
+) -> Result<u64> {
+    let amount_after_fees = get_swap_quote_exact_out(
+        token1_balance,
+        token2_balance,
+        token1_mint,
+        amount_to_take_from_the_pool,
+        token_to_take_from_the_pool,
+    )?;
+    before_fee(amount_after_fees, fee_bps)
+}
+
+// inverse of get_swap_quote, returns the post fee amount that has to be put into the pool,
+// rounded up so the pool never gives out more than the constant product allows
+pub fn get_swap_quote_exact_out(
+    token1_balance: u64,
+    token2_balance: u64,
+    token1_mint: Pubkey,
+    amount_to_take_from_the_pool: u64,
+    token_to_take_from_the_pool: Pubkey,
+) -> Result<u64> {
+    let (balance_in, balance_out) = if token_to_take_from_the_pool == token1_mint {
+        (token2_balance, token1_balance)
+    } else {
+        (token1_balance, token2_balance)
+    };
+    require!(
+        amount_to_take_from_the_pool < balance_out,
+        GeneralErrors::PoolInsufficient
+    );
+    let remaining_out = balance_out - amount_to_take_from_the_pool;
+    mul_div_ceil(balance_in, amount_to_take_from_the_pool, remaining_out)
+}
+
+// rounds down, whatever is left over stays with the remaining LPs
 pub fn tokens_to_return_while_remove_liquidity(
     lp_token_to_burn: u64,
     total_lp_tokens: u64,
     token_1_balance_in_pool: u64,
     token_2_balance_in_pool: u64,
 ) -> Result<(u64, u64)> {
-    let token1_return = lp_token_to_burn
-        .checked_mul(token_1_balance_in_pool)
-        .ok_or(GeneralErrors::MathOverflow)?
-        .checked_div(total_lp_tokens)
-        .ok_or(GeneralErrors::MathDivisionByZero)?;
-    let token2_return = lp_token_to_burn
-        .checked_mul(token_2_balance_in_pool)
-        .ok_or(GeneralErrors::MathOverflow)?
-        .checked_div(total_lp_tokens)
-        .ok_or(GeneralErrors::MathDivisionByZero)?;
+    let token1_return = mul_div_floor(lp_token_to_burn, token_1_balance_in_pool, total_lp_tokens)?;
+    let token2_return = mul_div_floor(lp_token_to_burn, token_2_balance_in_pool, total_lp_tokens)?;
     Ok((token1_return, token2_return))
 }
 
@@ -1,7 +1,7 @@
 import * as anchor from "@coral-xyz/anchor"
 import { Program } from "@coral-xyz/anchor"
 import { Amm } from "../target/types/amm"
-import { TOKEN_PROGRAM_ID, createAssociatedTokenAccountInstruction, createMint, getAccount, getAssociatedTokenAddress, getMint, mintTo } from "@solana/spl-token"
+import { TOKEN_PROGRAM_ID, createAssociatedTokenAccountInstruction, createTransferCheckedInstruction, createMint, getAccount, getAssociatedTokenAddress, getAssociatedTokenAddressSync, getMint, getOrCreateAssociatedTokenAccount, mintTo } from "@solana/spl-token"
 import { assert } from "chai"
 
 describe("amm initialize", () => {
@@ -23,6 +23,90 @@ describe("amm initialize", () => {
     let secondUserToken1Ata: anchor.web3.PublicKey
     let secondUserToken2Ata: anchor.web3.PublicKey
     let connection = anchor.getProvider().connection
+    const feeBps = 30
+    const observationCapacity = 32
+    const minimumLiquidity = 1_000
+    const protocolFeeBps = 1_000
+    const flashLoanFeeBps = 9
+    const treasury = anchor.web3.Keypair.generate()
+
+    const [configPda] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("config")],
+        program.programId
+    )
+
+    // pool creation derives its seeds from the mints sorted, so its PDAs are passed explicitly
+    const initializeAccounts = (first: anchor.web3.PublicKey, second: anchor.web3.PublicKey) => {
+        const [low, high] = Buffer.compare(first.toBuffer(), second.toBuffer()) <= 0 ? [first, second] : [second, first]
+        const pda = (label: string) => anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(label), low.toBuffer(), high.toBuffer()],
+            program.programId
+        )[0]
+        const poolAuthority = pda("pool_authority")
+        return {
+            signer: payer.publicKey,
+            dataAccount: pda("dataAccount"),
+            lpMint: pda("mint"),
+            lockedLpAccount: pda("locked_lp"),
+            poolAuthority,
+            config: configPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            mintToken1: first,
+            mintToken2: second,
+            token1Account: getAssociatedTokenAddressSync(first, poolAuthority, true),
+            token2Account: getAssociatedTokenAddressSync(second, poolAuthority, true),
+        }
+    }
+
+    const sleep = (ms: number) => new Promise(resolve => setTimeout(resolve, ms))
+
+    // reserve_out / reserve_in as a Q64.64 fixed point number, the way the program accumulates it
+    const q64Price = (numerator: anchor.BN, denominator: anchor.BN) =>
+        (BigInt(numerator.toString()) << BigInt(64)) / BigInt(denominator.toString())
+
+    // events go out through emit_cpi!, a self-CPI whose data is the event tag, the event
+    // discriminator and the borsh encoded event, so they are read back from the inner instructions
+    const emittedEvents = async (signature: string) => {
+        const tx = await connection.getTransaction(signature, {
+            commitment: "confirmed",
+            maxSupportedTransactionVersion: 0,
+        })
+        const accountKeys = tx.transaction.message.staticAccountKeys
+        return tx.meta.innerInstructions
+            .flatMap(inner => inner.instructions)
+            .filter(ix => accountKeys[ix.programIdIndex].equals(program.programId))
+            .map(ix => {
+                const data = anchor.utils.bytes.bs58.decode(ix.data)
+                return program.coder.events.decode(anchor.utils.bytes.base64.encode(data.subarray(8)))
+            })
+            .filter(event => event !== null)
+    }
+
+    const findEvent = async (signature: string, name: string) => {
+        const event = (await emittedEvents(signature)).find(e => e.name.toLowerCase() === name.toLowerCase())
+        assert.ok(event, `${name} should have been emitted`)
+        return event.data
+    }
+
+    // a second, empty pool on fresh mints for tests that need a clean state,
+    // with payer token accounts for both mints
+    const createPool = async (capacity: number) => {
+        let [first, second] = [
+            await createMint(connection, payer.payer, payer.publicKey, null, 6),
+            await createMint(connection, payer.payer, payer.publicKey, null, 6),
+        ]
+        if (Buffer.compare(first.toBuffer(), second.toBuffer()) > 0) {
+            [first, second] = [second, first]
+        }
+        const accounts = initializeAccounts(first, second)
+        await program.methods
+            .initialize(feeBps, capacity)
+            .accountsPartial(accounts)
+            .rpc()
+        const userToken1 = (await getOrCreateAssociatedTokenAccount(connection, payer.payer, first, payer.publicKey)).address
+        const userToken2 = (await getOrCreateAssociatedTokenAccount(connection, payer.payer, second, payer.publicKey)).address
+        return { ...accounts, userToken1, userToken2 }
+    }
 
     before(async () => {
         const tx = await connection.requestAirdrop(secondUser.publicKey, 6 * 1000000000)
@@ -41,16 +125,20 @@ describe("amm initialize", () => {
             null,
             6
         );
+        // the pool stores its mints sorted ascending, keep the test handles in the same order
+        if (Buffer.compare(mintToken1.toBuffer(), mintToken2.toBuffer()) > 0) {
+            [mintToken1, mintToken2] = [mintToken2, mintToken1]
+        }
         [dataAccountPda, dataAccountBump] = anchor.web3.PublicKey.findProgramAddressSync(
-            [Buffer.from("dataAccount"), program.programId.toBuffer()],
+            [Buffer.from("dataAccount"), mintToken1.toBuffer(), mintToken2.toBuffer()],
             program.programId
         );
         [lpMintPda, lpMintBump] = anchor.web3.PublicKey.findProgramAddressSync(
-            [Buffer.from("mint")],
+            [Buffer.from("mint"), mintToken1.toBuffer(), mintToken2.toBuffer()],
             program.programId
         )
         const [poolAuthorityPda, poolAuthorityBump] = await anchor.web3.PublicKey.findProgramAddress(
-            [Buffer.from("pool_authority")],
+            [Buffer.from("pool_authority"), mintToken1.toBuffer(), mintToken2.toBuffer()],
             program.programId
         )
         token1Ata = await getAssociatedTokenAddress(
@@ -91,25 +179,160 @@ describe("amm initialize", () => {
         )
     })
 
-    it("Initializes the AMM", async () => {
+    it("Initializes the protocol config", async () => {
+        const [programDataPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [program.programId.toBuffer()],
+            new anchor.web3.PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+        )
         await program.methods
-            .initialize()
+            .initializeConfig(treasury.publicKey, protocolFeeBps, flashLoanFeeBps)
             .accounts({
                 signer: payer.publicKey,
-                tokenProgram: TOKEN_PROGRAM_ID,
-                mintToken1: mintToken1,
-                mintToken2: mintToken2,
+                programData: programDataPda,
             })
             .rpc()
+        const config = await program.account.config.fetch(configPda)
+        assert.ok(config.admin.equals(payer.publicKey))
+        assert.ok(config.treasury.equals(treasury.publicKey))
+        assert.strictEqual(config.protocolFeeBps, protocolFeeBps)
+        assert.strictEqual(config.flashLoanFeeBps, flashLoanFeeBps)
+    })
+
+    it("Rejects identical mints", async () => {
+        try {
+            await program.methods
+                .initialize(feeBps, observationCapacity)
+                .accountsPartial(initializeAccounts(mintToken1, mintToken1))
+                .rpc()
+            assert.fail("initialize should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "IdenticalMints")
+        }
+    })
+
+    it("Initializes the AMM with the mints in either order", async () => {
+        // passed in descending order, the pool still lands on the sorted PDAs
+        const accounts = initializeAccounts(mintToken2, mintToken1)
+        assert.ok(accounts.dataAccount.equals(dataAccountPda))
+        await program.methods
+            .initialize(feeBps, observationCapacity)
+            .accountsPartial(accounts)
+            .rpc()
+        let secondPoolCreated = true
+        try {
+            await program.methods
+                .initialize(feeBps, observationCapacity)
+                .accountsPartial(initializeAccounts(mintToken1, mintToken2))
+                .rpc()
+        } catch (err) {
+            secondPoolCreated = false
+        }
+        assert.ok(!secondPoolCreated, "A/B and B/A must resolve to the same pool")
         const dataAccount = await program.account.dataAccount.fetch(dataAccountPda)
         assert.ok(dataAccount.token1Mint.equals(mintToken1))
         assert.ok(dataAccount.token2Mint.equals(mintToken2))
+        assert.strictEqual(dataAccount.feeBps, feeBps)
+        assert.ok(dataAccount.admin.equals(payer.publicKey))
+        const [observationsPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("observations"), dataAccountPda.toBuffer()],
+            program.programId
+        )
+        const observations = await program.account.observations.fetch(observationsPda)
+        assert.strictEqual(observations.capacity, observationCapacity)
+        assert.strictEqual(observations.observations.length, 1)
         const ata1Info = await getAccount(provider.connection, token1Ata)
         const ata2Info = await getAccount(provider.connection, token2Ata)
         assert.strictEqual(Number(ata1Info.amount), 0, "Token1 ATA should have 0 balance")
         assert.strictEqual(Number(ata2Info.amount), 0, "Token2 ATA should have 0 balance")
     })
 
+    it("Hands the pool admin over in two steps", async () => {
+        await program.methods
+            .proposeAdmin(secondUser.publicKey)
+            .accounts({ admin: payer.publicKey, dataAccount: dataAccountPda })
+            .rpc()
+        let dataAccount = await program.account.dataAccount.fetch(dataAccountPda)
+        assert.ok(dataAccount.admin.equals(payer.publicKey), "Admin should not change before acceptance")
+        assert.ok(dataAccount.pendingAdmin.equals(secondUser.publicKey))
+        await program.methods
+            .acceptAdmin()
+            .accounts({ pendingAdmin: secondUser.publicKey, dataAccount: dataAccountPda })
+            .signers([secondUser])
+            .rpc()
+        dataAccount = await program.account.dataAccount.fetch(dataAccountPda)
+        assert.ok(dataAccount.admin.equals(secondUser.publicKey))
+        assert.ok(dataAccount.pendingAdmin.equals(anchor.web3.PublicKey.default))
+        await program.methods
+            .updatePoolFee(feeBps)
+            .accounts({ admin: secondUser.publicKey, dataAccount: dataAccountPda })
+            .signers([secondUser])
+            .rpc()
+        try {
+            await program.methods
+                .updatePoolFee(feeBps)
+                .accounts({ admin: payer.publicKey, dataAccount: dataAccountPda })
+                .rpc()
+            assert.fail("the previous admin should no longer be allowed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "Unauthorized")
+        }
+    })
+
+    it("Gives a pool created by anyone to the config admin", async () => {
+        const [first, second] = [
+            await createMint(connection, payer.payer, payer.publicKey, null, 6),
+            await createMint(connection, payer.payer, payer.publicKey, null, 6),
+        ]
+        const accounts = { ...initializeAccounts(first, second), signer: secondUser.publicKey }
+        await program.methods
+            .initialize(feeBps, observationCapacity)
+            .accountsPartial(accounts)
+            .signers([secondUser])
+            .rpc()
+        const dataAccount = await program.account.dataAccount.fetch(accounts.dataAccount)
+        assert.ok(dataAccount.admin.equals(payer.publicKey), "The creator should not get the admin seat")
+    })
+
+    it("Hands the config admin over in two steps", async () => {
+        await program.methods
+            .proposeConfigAdmin(secondUser.publicKey)
+            .accounts({ admin: payer.publicKey })
+            .rpc()
+        let config = await program.account.config.fetch(configPda)
+        assert.ok(config.admin.equals(payer.publicKey), "Admin should not change before acceptance")
+        assert.ok(config.pendingAdmin.equals(secondUser.publicKey))
+        try {
+            await program.methods
+                .acceptConfigAdmin()
+                .accounts({ pendingAdmin: payer.publicKey })
+                .rpc()
+            assert.fail("only the proposed admin can accept")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "Unauthorized")
+        }
+        await program.methods
+            .acceptConfigAdmin()
+            .accounts({ pendingAdmin: secondUser.publicKey })
+            .signers([secondUser])
+            .rpc()
+        config = await program.account.config.fetch(configPda)
+        assert.ok(config.admin.equals(secondUser.publicKey))
+        assert.ok(config.pendingAdmin.equals(anchor.web3.PublicKey.default))
+
+        // hand it back, the rest of the suite runs the config as the payer
+        await program.methods
+            .proposeConfigAdmin(payer.publicKey)
+            .accounts({ admin: secondUser.publicKey })
+            .signers([secondUser])
+            .rpc()
+        await program.methods
+            .acceptConfigAdmin()
+            .accounts({ pendingAdmin: payer.publicKey })
+            .rpc()
+        config = await program.account.config.fetch(configPda)
+        assert.ok(config.admin.equals(payer.publicKey))
+    })
+
     it("Adds liquidity", async () => {
         const token1Amount = 1_000_000
         const token2Amount = 2_000_000
@@ -158,7 +381,7 @@ describe("amm initialize", () => {
             token2Amount
         )
         await program.methods
-            .addLiquidity(new anchor.BN(token1Amount), new anchor.BN(token2Amount))
+            .addLiquidity(new anchor.BN(token1Amount), new anchor.BN(token2Amount), new anchor.BN(0), null)
             .accounts({
                 signer: payer.publicKey,
                 tokenProgram: TOKEN_PROGRAM_ID,
@@ -192,6 +415,14 @@ describe("amm initialize", () => {
         let userLpAccountInfo = await getAccount(provider.connection, userLpAta)
         userLpAccountInfo = await getAccount(provider.connection, userLpAta)
         assert.ok(userLpAccountInfo.amount > 0, "User should receive LP tokens")
+        const [lockedLpPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("locked_lp"), mintToken1.toBuffer(), mintToken2.toBuffer()],
+            program.programId
+        )
+        const lockedLpAccountInfo = await getAccount(provider.connection, lockedLpPda)
+        assert.strictEqual(Number(lockedLpAccountInfo.amount), minimumLiquidity, "Minimum liquidity should be locked")
+        const lpSupply = Number((await getMint(provider.connection, lpMintPda)).supply)
+        assert.strictEqual(Number(userLpAccountInfo.amount), lpSupply - minimumLiquidity)
     })
 
     it("second user test", async () => {
@@ -241,8 +472,12 @@ describe("amm initialize", () => {
             payer.publicKey,
             token2Amount,
         )
+        const depositQuote = await program.methods
+            .quoteAddLiquidity(new anchor.BN(token1Amount), new anchor.BN(token2Amount))
+            .accounts({ dataAccount: dataAccountPda, lpMint: lpMintPda })
+            .view()
         await program.methods
-            .addLiquidity(new anchor.BN(token1Amount), new anchor.BN(token2Amount))
+            .addLiquidity(new anchor.BN(token1Amount), new anchor.BN(token2Amount), new anchor.BN(0), null)
             .accounts({
                 signer: secondUser.publicKey,
                 tokenProgram: TOKEN_PROGRAM_ID,
@@ -283,7 +518,58 @@ describe("amm initialize", () => {
         let userLpAccountInfo2 = await getAccount(provider.connection, userLpAta)
         userLpAccountInfo2 = await getAccount(provider.connection, userLpAta)
         assert.ok(userLpAccountInfo2.amount > 0, "User should receive LP tokens")
-        assert.ok(Number(userLpAccountInfo2.amount) == Math.floor(Number(userLpAccountInfo1.amount) / 2), "Varying data")
+        assert.strictEqual(userLpAccountInfo2.amount.toString(), depositQuote.lpAmount.toString(), "Deposit should mint the quoted LP")
+        assert.strictEqual(depositQuote.token1Amount.toNumber(), token1Amount)
+        assert.strictEqual(depositQuote.token2Amount.toNumber(), token2Amount)
+        assert.ok(Number(userLpAccountInfo2.amount) == Math.floor((Number(userLpAccountInfo1.amount) + minimumLiquidity) / 2), "Varying data")
+    })
+
+    it("Rejects a deposit that mints less than min_lp_out", async () => {
+        const lpSupply = (await getMint(connection, lpMintPda)).supply
+        try {
+            await program.methods
+                .addLiquidity(new anchor.BN(1_000), new anchor.BN(2_000), new anchor.BN(lpSupply.toString()), null)
+                .accounts({
+                    signer: secondUser.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1: mintToken1,
+                    mintToken2: mintToken2,
+                })
+                .signers([secondUser])
+                .rpc()
+            assert.fail("add liquidity should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "SlippageExceeded")
+        }
+    })
+
+    it("Returns MathOverflow for a deposit too large to price", async () => {
+        // matching u64::MAX of token1 needs more token2 than a u64 holds
+        const max = new anchor.BN("18446744073709551615")
+        try {
+            await program.methods
+                .quoteAddLiquidity(max, max)
+                .accounts({ dataAccount: dataAccountPda, lpMint: lpMintPda })
+                .view()
+            assert.fail("quote should have failed")
+        } catch (err) {
+            assert.include(err.toString(), "MathOverflow")
+        }
+        try {
+            await program.methods
+                .addLiquidity(max, max, new anchor.BN(0), null)
+                .accounts({
+                    signer: secondUser.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1: mintToken1,
+                    mintToken2: mintToken2,
+                })
+                .signers([secondUser])
+                .rpc()
+            assert.fail("add liquidity should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "MathOverflow")
+        }
     })
 
     it("Quotes output amount correctly", async () => {
@@ -292,21 +578,99 @@ describe("amm initialize", () => {
         const token2Balance = dataAccount.token2Balance.toNumber()
         const token1Mint = dataAccount.token1Mint
         const amountToQuote = 100_000
-        const quotedAmount = await program.methods
+        const quote = await program.methods
             .quote(token1Mint, new anchor.BN(amountToQuote))
             .accounts({
                 dataAccount: dataAccountPda,
             })
             .view()
-        const feeNumerator = BigInt(3)
-        const feeDenominator = BigInt(1000)
-        const feeAmount = BigInt(amountToQuote) * feeNumerator / feeDenominator
+        const feeNumerator = BigInt(dataAccount.feeBps)
+        const feeDenominator = BigInt(10_000)
+        // fee rounds up, output rounds down
+        const feeAmount = (BigInt(amountToQuote) * feeNumerator + feeDenominator - BigInt(1)) / feeDenominator
         const amountAfterFee = BigInt(amountToQuote) - feeAmount
-        const k = BigInt(token1Balance) * BigInt(token2Balance)
         const newT1Balance = BigInt(token1Balance) + amountAfterFee
-        const res = k / newT1Balance
-        const expected = BigInt(token2Balance) - res
-        assert.strictEqual(quotedAmount.toString(), expected.toString())
+        const expected = BigInt(token2Balance) * amountAfterFee / newT1Balance
+        assert.strictEqual(quote.amountOut.toString(), expected.toString())
+        assert.strictEqual(quote.fee.toString(), feeAmount.toString())
+        const protocolFee = feeAmount * BigInt(protocolFeeBps) / BigInt(10_000)
+        assert.strictEqual(
+            quote.token1BalanceAfter.toString(),
+            (BigInt(token1Balance) + BigInt(amountToQuote) - protocolFee).toString()
+        )
+        assert.strictEqual(quote.token2BalanceAfter.toString(), (BigInt(token2Balance) - expected).toString())
+        assert.ok(quote.priceImpactBps.toNumber() > 0, "A trade this size should move the price")
+    })
+
+    it("Rejects a swap whose output falls below the minimum", async () => {
+        const swapAmount = 100_000
+        const quotedAmount = (await program.methods
+            .quote(mintToken2, new anchor.BN(swapAmount))
+            .accounts({ dataAccount: dataAccountPda })
+            .view()).amountOut
+        try {
+            await program.methods
+                .swap(new anchor.BN(swapAmount), mintToken2, quotedAmount.addn(1), null)
+                .accounts({
+                    signer: secondUser.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1,
+                    mintToken2,
+                })
+                .signers([secondUser])
+                .rpc()
+            assert.fail("swap should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "SlippageExceeded")
+        }
+    })
+
+    it("Rejects a swap past its deadline", async () => {
+        const slot = await connection.getSlot()
+        const now = await connection.getBlockTime(slot)
+        try {
+            await program.methods
+                .swap(new anchor.BN(100_000), mintToken2, new anchor.BN(0), new anchor.BN(now - 60))
+                .accounts({
+                    signer: secondUser.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1,
+                    mintToken2,
+                })
+                .signers([secondUser])
+                .rpc()
+            assert.fail("swap should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "Expired")
+        }
+    })
+
+    it("Rejects swapping or quoting a mint outside the pool", async () => {
+        const foreignMint = anchor.web3.Keypair.generate().publicKey
+        try {
+            await program.methods
+                .quote(foreignMint, new anchor.BN(100_000))
+                .accounts({ dataAccount: dataAccountPda })
+                .view()
+            assert.fail("quote should have failed")
+        } catch (err) {
+            assert.include(err.toString(), "MintNotInPool")
+        }
+        try {
+            await program.methods
+                .swap(new anchor.BN(100_000), foreignMint, new anchor.BN(0), null)
+                .accounts({
+                    signer: secondUser.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1,
+                    mintToken2,
+                })
+                .signers([secondUser])
+                .rpc()
+            assert.fail("swap should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "MintNotInPool")
+        }
     })
 
     it("Swaps token1 for token2 with full balance checks", async () => {
@@ -320,7 +684,7 @@ describe("amm initialize", () => {
             swapAmount
         )
         const [poolAuthorityPda] = anchor.web3.PublicKey.findProgramAddressSync(
-            [Buffer.from("pool_authority")],
+            [Buffer.from("pool_authority"), mintToken1.toBuffer(), mintToken2.toBuffer()],
             program.programId
         )
         const poolToken1Ata = await getAssociatedTokenAddress(mintToken1, poolAuthorityPda, true)
@@ -330,12 +694,12 @@ describe("amm initialize", () => {
         const poolToken1Before = Number((await getAccount(connection, poolToken1Ata)).amount)
         const poolToken2Before = Number((await getAccount(connection, poolToken2Ata)).amount)
         let dataAcc = await program.account.dataAccount.fetch(dataAccountPda)
-        const quotedAmount = await program.methods
+        const quotedAmount = (await program.methods
             .quote(mintToken1, new anchor.BN(swapAmount))
             .accounts({ dataAccount: dataAccountPda })
-            .view()
+            .view()).amountOut
         await program.methods
-            .swap(new anchor.BN(swapAmount), mintToken1)
+            .swap(new anchor.BN(swapAmount), mintToken1, quotedAmount, null)
             .accounts({
                 signer: secondUser.publicKey,
                 tokenProgram: TOKEN_PROGRAM_ID,
@@ -366,7 +730,7 @@ describe("amm initialize", () => {
             swapAmount
         )
         const [poolAuthorityPda] = anchor.web3.PublicKey.findProgramAddressSync(
-            [Buffer.from("pool_authority")],
+            [Buffer.from("pool_authority"), mintToken1.toBuffer(), mintToken2.toBuffer()],
             program.programId
         )
         const poolToken1Ata = await getAssociatedTokenAddress(mintToken1, poolAuthorityPda, true)
@@ -376,12 +740,12 @@ describe("amm initialize", () => {
         const poolToken1Before = Number((await getAccount(connection, poolToken1Ata)).amount)
         const poolToken2Before = Number((await getAccount(connection, poolToken2Ata)).amount)
         let dataAcc = await program.account.dataAccount.fetch(dataAccountPda)
-        const quotedAmount = await program.methods
+        const quotedAmount = (await program.methods
             .quote(mintToken2, new anchor.BN(swapAmount))
             .accounts({ dataAccount: dataAccountPda })
-            .view()
+            .view()).amountOut
         await program.methods
-            .swap(new anchor.BN(swapAmount), mintToken2)
+            .swap(new anchor.BN(swapAmount), mintToken2, quotedAmount, null)
             .accounts({
                 signer: secondUser.publicKey,
                 tokenProgram: TOKEN_PROGRAM_ID,
@@ -401,19 +765,510 @@ describe("amm initialize", () => {
         assert.strictEqual(poolToken1After, poolToken1Before - Number(quotedAmount), "Pool token1 should decrease by quoted amount")
     })
 
+    it("Swaps token1 for an exact amount of token2", async () => {
+        const amountOut = 50_000
+        const quotedIn = await program.methods
+            .quoteExactOut(mintToken2, new anchor.BN(amountOut))
+            .accounts({ dataAccount: dataAccountPda })
+            .view()
+        await mintTo(
+            connection,
+            payer.payer,
+            mintToken1,
+            secondUserToken1Ata,
+            payer.publicKey,
+            Number(quotedIn)
+        )
+        const userToken1Before = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        const userToken2Before = Number((await getAccount(connection, secondUserToken2Ata)).amount)
+        await program.methods
+            .swapExactOut(new anchor.BN(amountOut), quotedIn, mintToken2, null)
+            .accounts({
+                signer: secondUser.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+            })
+            .signers([secondUser])
+            .rpc()
+        const userToken1After = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        const userToken2After = Number((await getAccount(connection, secondUserToken2Ata)).amount)
+        assert.strictEqual(userToken2After, userToken2Before + amountOut, "User should receive the exact amount out")
+        assert.strictEqual(userToken1After, userToken1Before - Number(quotedIn), "User should pay the quoted amount in")
+    })
+
+    it("Rejects an exact-out swap above the maximum input or for a foreign mint", async () => {
+        const amountOut = 50_000
+        const quotedIn = await program.methods
+            .quoteExactOut(mintToken2, new anchor.BN(amountOut))
+            .accounts({ dataAccount: dataAccountPda })
+            .view()
+        const accounts = {
+            signer: secondUser.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            mintToken1,
+            mintToken2,
+        }
+        try {
+            await program.methods
+                .swapExactOut(new anchor.BN(amountOut), quotedIn.subn(1), mintToken2, null)
+                .accounts(accounts)
+                .signers([secondUser])
+                .rpc()
+            assert.fail("swap should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "ExcessiveInputAmount")
+        }
+        try {
+            await program.methods
+                .swapExactOut(new anchor.BN(amountOut), quotedIn, anchor.web3.Keypair.generate().publicKey, null)
+                .accounts(accounts)
+                .signers([secondUser])
+                .rpc()
+            assert.fail("swap should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "MintNotInPool")
+        }
+    })
+
+    it("Accumulates price times elapsed seconds on a swap", async () => {
+        const before = await program.account.dataAccount.fetch(dataAccountPda)
+        // let the cluster clock move past the last update
+        await sleep(2_000)
+        const swapAmount = 10_000
+        await mintTo(connection, payer.payer, mintToken1, secondUserToken1Ata, payer.publicKey, swapAmount)
+        await program.methods
+            .swap(new anchor.BN(swapAmount), mintToken1, new anchor.BN(0), null)
+            .accounts({
+                signer: secondUser.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+            })
+            .signers([secondUser])
+            .rpc()
+        const after = await program.account.dataAccount.fetch(dataAccountPda)
+        const elapsed = BigInt(after.lastPriceUpdate.sub(before.lastPriceUpdate).toString())
+        assert.ok(elapsed > BigInt(0), "The clock should have moved between the two updates")
+        // the prices that held until the swap are the ones from before it
+        const price1 = q64Price(before.token2Balance, before.token1Balance)
+        const price2 = q64Price(before.token1Balance, before.token2Balance)
+        assert.strictEqual(
+            BigInt(after.price1Cumulative.toString()) - BigInt(before.price1Cumulative.toString()),
+            price1 * elapsed
+        )
+        assert.strictEqual(
+            BigInt(after.price2Cumulative.toString()) - BigInt(before.price2Cumulative.toString()),
+            price2 * elapsed
+        )
+    })
+
+    it("Emits swap and liquidity events through self-CPI", async () => {
+        const swapAmount = 20_000
+        await mintTo(connection, payer.payer, mintToken1, secondUserToken1Ata, payer.publicKey, swapAmount)
+        const quote = await program.methods
+            .quote(mintToken1, new anchor.BN(swapAmount))
+            .accounts({ dataAccount: dataAccountPda })
+            .view()
+        const swapSignature = await program.methods
+            .swap(new anchor.BN(swapAmount), mintToken1, new anchor.BN(0), null)
+            .accounts({
+                signer: secondUser.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+            })
+            .signers([secondUser])
+            .rpc({ commitment: "confirmed" })
+        const swapped = await findEvent(swapSignature, "Swapped")
+        assert.ok(swapped.pool.equals(dataAccountPda))
+        assert.ok(swapped.user.equals(secondUser.publicKey))
+        assert.ok(swapped.tokenIn.equals(mintToken1))
+        assert.ok(swapped.tokenOut.equals(mintToken2))
+        assert.strictEqual(swapped.amountIn.toNumber(), swapAmount)
+        assert.strictEqual(swapped.amountOut.toString(), quote.amountOut.toString())
+        assert.strictEqual(swapped.fee.toString(), quote.fee.toString())
+        assert.strictEqual(
+            swapped.protocolFee.toNumber(),
+            Math.floor(quote.fee.toNumber() * protocolFeeBps / 10_000)
+        )
+        assert.strictEqual(swapped.token1Balance.toString(), quote.token1BalanceAfter.toString())
+        assert.strictEqual(swapped.token2Balance.toString(), quote.token2BalanceAfter.toString())
+        assert.strictEqual(swapped.lpSupply.toString(), (await getMint(connection, lpMintPda)).supply.toString())
+
+        const token1Amount = 10_000
+        const token2Amount = 40_000
+        await mintTo(connection, payer.payer, mintToken1, secondUserToken1Ata, payer.publicKey, token1Amount)
+        await mintTo(connection, payer.payer, mintToken2, secondUserToken2Ata, payer.publicKey, token2Amount)
+        const addQuote = await program.methods
+            .quoteAddLiquidity(new anchor.BN(token1Amount), new anchor.BN(token2Amount))
+            .accounts({ dataAccount: dataAccountPda, lpMint: lpMintPda })
+            .view()
+        const addSignature = await program.methods
+            .addLiquidity(new anchor.BN(token1Amount), new anchor.BN(token2Amount), new anchor.BN(0), null)
+            .accounts({
+                signer: secondUser.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+            })
+            .signers([secondUser])
+            .rpc({ commitment: "confirmed" })
+        const added = await findEvent(addSignature, "LiquidityAdded")
+        let dataAccount = await program.account.dataAccount.fetch(dataAccountPda)
+        assert.ok(added.pool.equals(dataAccountPda))
+        assert.ok(added.user.equals(secondUser.publicKey))
+        assert.strictEqual(added.token1Amount.toString(), addQuote.token1Amount.toString())
+        assert.strictEqual(added.token2Amount.toString(), addQuote.token2Amount.toString())
+        assert.strictEqual(added.lpMinted.toString(), addQuote.lpAmount.toString())
+        assert.strictEqual(added.lpLocked.toNumber(), 0)
+        assert.strictEqual(added.token1Balance.toString(), dataAccount.token1Balance.toString())
+        assert.strictEqual(added.token2Balance.toString(), dataAccount.token2Balance.toString())
+        assert.strictEqual(added.lpSupply.toString(), (await getMint(connection, lpMintPda)).supply.toString())
+
+        const removeQuote = await program.methods
+            .quoteRemoveLiquidity(added.lpMinted)
+            .accounts({ dataAccount: dataAccountPda, lpMint: lpMintPda })
+            .view()
+        const removeSignature = await program.methods
+            .removeLiquidity(added.lpMinted, new anchor.BN(0), new anchor.BN(0), null)
+            .accounts({
+                signer: secondUser.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+            })
+            .signers([secondUser])
+            .rpc({ commitment: "confirmed" })
+        const removed = await findEvent(removeSignature, "LiquidityRemoved")
+        dataAccount = await program.account.dataAccount.fetch(dataAccountPda)
+        assert.ok(removed.pool.equals(dataAccountPda))
+        assert.ok(removed.user.equals(secondUser.publicKey))
+        assert.strictEqual(removed.token1Amount.toString(), removeQuote.token1Amount.toString())
+        assert.strictEqual(removed.token2Amount.toString(), removeQuote.token2Amount.toString())
+        assert.strictEqual(removed.lpBurned.toString(), added.lpMinted.toString())
+        assert.strictEqual(removed.token1Balance.toString(), dataAccount.token1Balance.toString())
+        assert.strictEqual(removed.token2Balance.toString(), dataAccount.token2Balance.toString())
+        assert.strictEqual(removed.lpSupply.toString(), (await getMint(connection, lpMintPda)).supply.toString())
+    })
+
+    it("Swaps along a route and rejects a route that repeats a pool", async () => {
+        const swapAmount = 30_000
+        await mintTo(connection, payer.payer, mintToken1, secondUserToken1Ata, payer.publicKey, swapAmount)
+        const [poolAuthorityPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("pool_authority"), mintToken1.toBuffer(), mintToken2.toBuffer()],
+            program.programId
+        )
+        const [observationsPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("observations"), dataAccountPda.toBuffer()],
+            program.programId
+        )
+        const hop = [
+            { pubkey: dataAccountPda, isSigner: false, isWritable: true },
+            { pubkey: observationsPda, isSigner: false, isWritable: true },
+            { pubkey: poolAuthorityPda, isSigner: false, isWritable: false },
+            { pubkey: lpMintPda, isSigner: false, isWritable: false },
+            { pubkey: token1PoolAta, isSigner: false, isWritable: true },
+            { pubkey: token2PoolAta, isSigner: false, isWritable: true },
+            { pubkey: mintToken1, isSigner: false, isWritable: false },
+            { pubkey: mintToken2, isSigner: false, isWritable: false },
+        ]
+        const accounts = {
+            signer: secondUser.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            userSourceTokenAccount: secondUserToken1Ata,
+            userDestinationTokenAccount: secondUserToken2Ata,
+        }
+        const quotedAmount = (await program.methods
+            .quote(mintToken1, new anchor.BN(swapAmount))
+            .accounts({ dataAccount: dataAccountPda })
+            .view()).amountOut
+        const userToken2Before = Number((await getAccount(connection, secondUserToken2Ata)).amount)
+        await program.methods
+            .swapRoute(new anchor.BN(swapAmount), quotedAmount, null)
+            .accounts(accounts)
+            .remainingAccounts(hop)
+            .signers([secondUser])
+            .rpc()
+        const userToken2After = Number((await getAccount(connection, secondUserToken2Ata)).amount)
+        assert.strictEqual(userToken2After, userToken2Before + Number(quotedAmount), "Route should pay the quoted amount")
+        try {
+            await program.methods
+                .swapRoute(new anchor.BN(swapAmount), new anchor.BN(0), null)
+                .accounts({
+                    ...accounts,
+                    userDestinationTokenAccount: secondUserToken1Ata,
+                })
+                .remainingAccounts([...hop, ...hop])
+                .signers([secondUser])
+                .rpc()
+            assert.fail("route should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "InvalidRoute")
+        }
+    })
+
+    it("Flash swaps token2 and pays for it in token1 from the callback", async () => {
+        const amountOut = 20_000
+        const quotedIn = await program.methods
+            .quoteExactOut(mintToken2, new anchor.BN(amountOut))
+            .accounts({ dataAccount: dataAccountPda })
+            .view()
+        await mintTo(
+            connection,
+            payer.payer,
+            mintToken1,
+            secondUserToken1Ata,
+            payer.publicKey,
+            Number(quotedIn)
+        )
+        const userToken1Before = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        const userToken2Before = Number((await getAccount(connection, secondUserToken2Ata)).amount)
+        // the token program itself serves as the callback, repaying straight into the vault
+        const repay = createTransferCheckedInstruction(
+            secondUserToken1Ata,
+            mintToken1,
+            token1PoolAta,
+            secondUser.publicKey,
+            BigInt(quotedIn.toString()),
+            6
+        )
+        await program.methods
+            .flashSwap(new anchor.BN(0), new anchor.BN(amountOut), repay.data)
+            .accounts({
+                signer: secondUser.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+                recipientToken1Account: secondUserToken1Ata,
+                recipientToken2Account: secondUserToken2Ata,
+                callbackProgram: TOKEN_PROGRAM_ID,
+            })
+            .remainingAccounts(repay.keys)
+            .signers([secondUser])
+            .rpc()
+        const userToken1After = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        const userToken2After = Number((await getAccount(connection, secondUserToken2Ata)).amount)
+        assert.strictEqual(userToken2After, userToken2Before + amountOut, "User should receive the borrowed amount")
+        assert.strictEqual(userToken1After, userToken1Before - Number(quotedIn), "User should pay the quoted amount in")
+    })
+
+    it("Rejects a flash swap that is not paid back", async () => {
+        await mintTo(connection, payer.payer, mintToken1, secondUserToken1Ata, payer.publicKey, 1)
+        const underpay = createTransferCheckedInstruction(
+            secondUserToken1Ata,
+            mintToken1,
+            token1PoolAta,
+            secondUser.publicKey,
+            BigInt(1),
+            6
+        )
+        try {
+            await program.methods
+                .flashSwap(new anchor.BN(0), new anchor.BN(20_000), underpay.data)
+                .accounts({
+                    signer: secondUser.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1,
+                    mintToken2,
+                    recipientToken1Account: secondUserToken1Ata,
+                    recipientToken2Account: secondUserToken2Ata,
+                    callbackProgram: TOKEN_PROGRAM_ID,
+                })
+                .remainingAccounts(underpay.keys)
+                .signers([secondUser])
+                .rpc()
+            assert.fail("flash swap should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "InvariantViolated")
+        }
+    })
+
+    it("Borrows and repays a flash loan with the fee left to the LPs", async () => {
+        const amount = 100_000
+        const fee = Math.ceil(amount * flashLoanFeeBps / 10_000)
+        await mintTo(connection, payer.payer, mintToken1, secondUserToken1Ata, payer.publicKey, fee)
+        const accounts = {
+            signer: secondUser.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            mintToken1,
+            mintToken2,
+            borrowerTokenAccount: secondUserToken1Ata,
+        }
+        const before = await program.account.dataAccount.fetch(dataAccountPda)
+        const userToken1Before = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        const tx = new anchor.web3.Transaction().add(
+            await program.methods
+                .flashLoanBorrow(mintToken1, new anchor.BN(amount))
+                .accounts(accounts)
+                .instruction(),
+            await program.methods
+                .flashLoanRepay()
+                .accounts(accounts)
+                .instruction()
+        )
+        await provider.sendAndConfirm(tx, [secondUser])
+        const after = await program.account.dataAccount.fetch(dataAccountPda)
+        const userToken1After = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        assert.strictEqual(userToken1After, userToken1Before - fee, "Borrower should pay only the fee")
+        assert.strictEqual(after.token1Balance.toNumber(), before.token1Balance.toNumber() + fee, "Fee should go to the reserves")
+        assert.strictEqual(after.flashLoanAmount.toNumber(), 0)
+    })
+
+    it("Rejects a flash loan without a repay", async () => {
+        try {
+            await program.methods
+                .flashLoanBorrow(mintToken1, new anchor.BN(100_000))
+                .accounts({
+                    signer: secondUser.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1,
+                    mintToken2,
+                    borrowerTokenAccount: secondUserToken1Ata,
+                })
+                .signers([secondUser])
+                .rpc()
+            assert.fail("flash loan should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "FlashLoanNotRepaid")
+        }
+    })
+
+    it("Deposits token1 alone and gets LP back", async () => {
+        const amount = 50_000
+        await mintTo(connection, payer.payer, mintToken1, secondUserToken1Ata, payer.publicKey, amount)
+        const userLpAta = await getAssociatedTokenAddress(lpMintPda, secondUser.publicKey)
+        const userToken1Before = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        const userToken2Before = Number((await getAccount(connection, secondUserToken2Ata)).amount)
+        const userLpBefore = Number((await getAccount(connection, userLpAta)).amount)
+        const signature = await program.methods
+            .depositSingleToken(mintToken1, new anchor.BN(amount), new anchor.BN(1), null)
+            .accounts({
+                signer: secondUser.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+            })
+            .signers([secondUser])
+            .rpc({ commitment: "confirmed" })
+        const userToken1After = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        const userToken2After = Number((await getAccount(connection, secondUserToken2Ata)).amount)
+        const userLpAfter = Number((await getAccount(connection, userLpAta)).amount)
+        const spent = userToken1Before - userToken1After
+        const refund = userToken2After - userToken2Before
+        // the swapped share is sized so both halves match the pool ratio, only rounding is left over
+        assert(spent <= amount && amount - spent <= 3, `User should spend the whole deposit, spent ${spent}`)
+        assert(refund >= 0 && refund <= 3, `Token2 refund should be rounding dust, got ${refund}`)
+
+        const swapped = await findEvent(signature, "Swapped")
+        const added = await findEvent(signature, "LiquidityAdded")
+        assert.strictEqual(spent, swapped.amountIn.toNumber() + added.token1Amount.toNumber())
+        assert.strictEqual(refund, swapped.amountOut.toNumber() - added.token2Amount.toNumber())
+        assert.strictEqual(userLpAfter - userLpBefore, added.lpMinted.toNumber())
+        // LP is priced against the reserves the swap left behind, before the deposit landed
+        const deposit1 = BigInt(added.token1Amount.toString())
+        const deposit2 = BigInt(added.token2Amount.toString())
+        const reserve1 = BigInt(added.token1Balance.toString()) - deposit1
+        const reserve2 = BigInt(added.token2Balance.toString()) - deposit2
+        const supply = BigInt(added.lpSupply.toString()) - BigInt(added.lpMinted.toString())
+        const fromToken1 = deposit1 * supply / reserve1
+        const fromToken2 = deposit2 * supply / reserve2
+        const expectedLp = fromToken1 < fromToken2 ? fromToken1 : fromToken2
+        assert.strictEqual(added.lpMinted.toString(), expectedLp.toString())
+    })
+
+    it("Withdraws LP into token2 alone", async () => {
+        const userLpAta = await getAssociatedTokenAddress(lpMintPda, secondUser.publicKey)
+        const userLpBefore = Number((await getAccount(connection, userLpAta)).amount)
+        const userToken1Before = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        const userToken2Before = Number((await getAccount(connection, secondUserToken2Ata)).amount)
+        const lpAmount = Math.floor(userLpBefore / 2)
+        await program.methods
+            .withdrawSingleToken(new anchor.BN(lpAmount), mintToken2, new anchor.BN(1), null)
+            .accounts({
+                signer: secondUser.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+            })
+            .signers([secondUser])
+            .rpc()
+        const userLpAfter = Number((await getAccount(connection, userLpAta)).amount)
+        const userToken1After = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        const userToken2After = Number((await getAccount(connection, secondUserToken2Ata)).amount)
+        assert.strictEqual(userLpAfter, userLpBefore - lpAmount, "LP should be burnt")
+        assert.strictEqual(userToken1After, userToken1Before, "No token1 should be paid out")
+        assert(userToken2After > userToken2Before, "Token2 should be paid out")
+    })
+
+    it("Rejects a single token withdrawal of more LP than exists", async () => {
+        const lpSupply = (await getMint(connection, lpMintPda)).supply
+        try {
+            await program.methods
+                .withdrawSingleToken(new anchor.BN((lpSupply + BigInt(1)).toString()), mintToken2, new anchor.BN(0), null)
+                .accounts({
+                    signer: secondUser.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1,
+                    mintToken2,
+                })
+                .signers([secondUser])
+                .rpc()
+            assert.fail("withdrawal should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "MathUnderflow")
+        }
+    })
+
+    it("Skims and syncs tokens sent straight to the vaults", async () => {
+        const donation = 10_000
+        const recipientBefore = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        await mintTo(connection, payer.payer, mintToken1, token1PoolAta, payer.publicKey, donation)
+        await program.methods
+            .skim()
+            .accounts({
+                signer: payer.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+                recipientToken1Account: secondUserToken1Ata,
+                recipientToken2Account: secondUserToken2Ata,
+            })
+            .rpc()
+        const recipientAfter = Number((await getAccount(connection, secondUserToken1Ata)).amount)
+        assert.strictEqual(recipientAfter, recipientBefore + donation, "Skim should hand out the donation")
+
+        const before = await program.account.dataAccount.fetch(dataAccountPda)
+        await mintTo(connection, payer.payer, mintToken1, token1PoolAta, payer.publicKey, donation)
+        await program.methods
+            .sync()
+            .accounts({
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+            })
+            .rpc()
+        const after = await program.account.dataAccount.fetch(dataAccountPda)
+        assert.strictEqual(after.token1Balance.toNumber(), before.token1Balance.toNumber() + donation)
+        assert.strictEqual(after.token2Balance.toNumber(), before.token2Balance.toNumber())
+    })
+
     it("Burns LP tokens and withdraws liquidity correctly for second user", async () => {
         const token1UserAta = await getAssociatedTokenAddress(mintToken1, payer.publicKey);
         const token2UserAta = await getAssociatedTokenAddress(mintToken2, payer.publicKey);
         const userLpAta = await getAssociatedTokenAddress(lpMintPda, payer.publicKey);
         const [poolAuthorityPda] = await anchor.web3.PublicKey.findProgramAddress(
-            [Buffer.from("pool_authority")],
+            [Buffer.from("pool_authority"), mintToken1.toBuffer(), mintToken2.toBuffer()],
             program.programId
         );
 
         const poolToken1Ata = await getAssociatedTokenAddress(mintToken1, poolAuthorityPda, true);
         const poolToken2Ata = await getAssociatedTokenAddress(mintToken2, poolAuthorityPda, true);
 
-        // Fetch balances before burning
+        // Fetch balances before burning, accrued protocol fees are not part of the reserves
+        const dataAccount = await program.account.dataAccount.fetch(dataAccountPda);
+        const protocolFeesToken1 = dataAccount.protocolFeesToken1.toNumber();
+        const protocolFeesToken2 = dataAccount.protocolFeesToken2.toNumber();
         const userToken1Before = Number((await getAccount(connection, token1UserAta)).amount);
         const userToken2Before = Number((await getAccount(connection, token2UserAta)).amount);
         const poolToken1Before = Number((await getAccount(connection, poolToken1Ata)).amount);
@@ -424,8 +1279,41 @@ describe("amm initialize", () => {
         // Assert user has LP tokens to burn
         assert(userLpBefore > 0, "User should have LP tokens before burning");
 
+        // Calculate expected token withdrawals proportionally
+        const burnRatio = userLpBefore / totalLpBefore;
+        const expectedUserToken1Increase = Math.floor((poolToken1Before - protocolFeesToken1) * burnRatio);
+        const expectedUserToken2Increase = Math.floor((poolToken2Before - protocolFeesToken2) * burnRatio);
+
+        // Asking for more than the proportional share must fail
+        try {
+            await program.methods.removeLiquidity(
+                new anchor.BN(userLpBefore),
+                new anchor.BN(expectedUserToken1Increase + 10),
+                new anchor.BN(0),
+                null,
+            ).accounts({
+                signer: payer.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1: mintToken1,
+                mintToken2: mintToken2,
+            }).rpc();
+            assert.fail("remove liquidity should have failed");
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "WithdrawalBelowMinimum");
+        }
+
+        const withdrawQuote = await program.methods
+            .quoteRemoveLiquidity(new anchor.BN(userLpBefore))
+            .accounts({ dataAccount: dataAccountPda, lpMint: lpMintPda })
+            .view();
+
         // Call removeLiquidity with user's full LP balance
-        await program.methods.removeLiquidity(new anchor.BN(userLpBefore)).accounts({
+        await program.methods.removeLiquidity(
+            new anchor.BN(userLpBefore),
+            new anchor.BN(expectedUserToken1Increase - 5),
+            new anchor.BN(expectedUserToken2Increase - 5),
+            null,
+        ).accounts({
             signer: payer.publicKey,
             tokenProgram: TOKEN_PROGRAM_ID,
             mintToken1: mintToken1,
@@ -440,6 +1328,9 @@ describe("amm initialize", () => {
         const userLpAfter = Number((await getAccount(connection, userLpAta)).amount);
         const totalLpAfter = Number((await getMint(connection, lpMintPda)).supply);
 
+        assert.strictEqual(userToken1After - userToken1Before, withdrawQuote.token1Amount.toNumber(), "Withdrawal should pay the quoted token1");
+        assert.strictEqual(userToken2After - userToken2Before, withdrawQuote.token2Amount.toNumber(), "Withdrawal should pay the quoted token2");
+
         // Assert user LP tokens burned fully
         assert.strictEqual(userLpAfter, 0, "User LP tokens should be zero after burning");
 
@@ -450,11 +1341,6 @@ describe("amm initialize", () => {
             `Total LP supply after burning should decrease by burned amount. Expected ~${expectedTotalLpAfter}, got ${totalLpAfter}`
         );
 
-        // Calculate expected token withdrawals proportionally
-        const burnRatio = userLpBefore / totalLpBefore;
-        const expectedUserToken1Increase = Math.floor(poolToken1Before * burnRatio);
-        const expectedUserToken2Increase = Math.floor(poolToken2Before * burnRatio);
-
         // Check user token balances increased as expected (allow small tolerance)
         assert.ok(
             Math.abs(userToken1After - userToken1Before - expectedUserToken1Increase) <= 5,
@@ -475,5 +1361,264 @@ describe("amm initialize", () => {
             `Pool token2 balance should decrease by ~${expectedUserToken2Increase}`
         );
     });
-})
 
+    it("Pauses swaps on the pool and deposits everywhere", async () => {
+        const pauseSwaps = 1
+        const pauseDeposits = 2
+        // the pool admin was handed to the second user earlier on
+        await program.methods
+            .setPoolPause(pauseSwaps)
+            .accounts({ admin: secondUser.publicKey, dataAccount: dataAccountPda })
+            .signers([secondUser])
+            .rpc()
+        try {
+            await program.methods
+                .swap(new anchor.BN(1_000), mintToken1, new anchor.BN(0), null)
+                .accounts({
+                    signer: secondUser.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1,
+                    mintToken2,
+                })
+                .signers([secondUser])
+                .rpc()
+            assert.fail("swap should have been paused")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "Paused")
+        }
+        await program.methods
+            .setPoolPause(0)
+            .accounts({ admin: secondUser.publicKey, dataAccount: dataAccountPda })
+            .signers([secondUser])
+            .rpc()
+
+        await program.methods
+            .setGlobalPause(pauseDeposits)
+            .accounts({ admin: payer.publicKey })
+            .rpc()
+        try {
+            await program.methods
+                .addLiquidity(new anchor.BN(1_000), new anchor.BN(2_000), new anchor.BN(0), null)
+                .accounts({
+                    signer: secondUser.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1,
+                    mintToken2,
+                })
+                .signers([secondUser])
+                .rpc()
+            assert.fail("deposit should have been paused")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "Paused")
+        }
+        await program.methods
+            .setGlobalPause(0)
+            .accounts({ admin: payer.publicKey })
+            .rpc()
+    })
+
+    it("Leaves withdrawal pauses to the config admin", async () => {
+        const pauseWithdrawals = 4
+        const pauseAll = 7
+        // the second user holds the pool admin seat but not the config one
+        for (const flags of [pauseWithdrawals, pauseAll]) {
+            try {
+                await program.methods
+                    .setPoolPause(flags)
+                    .accounts({ admin: secondUser.publicKey, dataAccount: dataAccountPda })
+                    .signers([secondUser])
+                    .rpc()
+                assert.fail("pool admin should not freeze withdrawals")
+            } catch (err) {
+                assert.strictEqual(err.error?.errorCode?.code, "Unauthorized")
+            }
+        }
+        await program.methods
+            .overridePoolPause(pauseWithdrawals)
+            .accounts({ admin: payer.publicKey, dataAccount: dataAccountPda })
+            .rpc()
+        // clearing its own flags leaves the config admin's withdrawal pause in place
+        await program.methods
+            .setPoolPause(0)
+            .accounts({ admin: secondUser.publicKey, dataAccount: dataAccountPda })
+            .signers([secondUser])
+            .rpc()
+        let dataAccount = await program.account.dataAccount.fetch(dataAccountPda)
+        assert.strictEqual(dataAccount.paused, pauseWithdrawals)
+        await program.methods
+            .overridePoolPause(0)
+            .accounts({ admin: payer.publicKey, dataAccount: dataAccountPda })
+            .rpc()
+        dataAccount = await program.account.dataAccount.fetch(dataAccountPda)
+        assert.strictEqual(dataAccount.paused, 0)
+    })
+
+    it("Collects accrued protocol fees into the treasury", async () => {
+        const before = await program.account.dataAccount.fetch(dataAccountPda)
+        assert.ok(
+            before.protocolFeesToken1.toNumber() > 0 && before.protocolFeesToken2.toNumber() > 0,
+            "Swaps should have accrued protocol fees on both sides"
+        )
+        await program.methods
+            .collectProtocolFees()
+            .accounts({
+                signer: payer.publicKey,
+                treasury: treasury.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1,
+                mintToken2,
+            })
+            .rpc()
+        const treasuryToken1Ata = await getAssociatedTokenAddress(mintToken1, treasury.publicKey)
+        const treasuryToken2Ata = await getAssociatedTokenAddress(mintToken2, treasury.publicKey)
+        assert.strictEqual(
+            Number((await getAccount(connection, treasuryToken1Ata)).amount),
+            before.protocolFeesToken1.toNumber()
+        )
+        assert.strictEqual(
+            Number((await getAccount(connection, treasuryToken2Ata)).amount),
+            before.protocolFeesToken2.toNumber()
+        )
+        const after = await program.account.dataAccount.fetch(dataAccountPda)
+        assert.strictEqual(after.protocolFeesToken1.toNumber(), 0)
+        assert.strictEqual(after.protocolFeesToken2.toNumber(), 0)
+    })
+
+    it("Refuses to sync a pool before its first deposit", async () => {
+        const pool = await createPool(observationCapacity)
+        const donation = 5_000
+        await mintTo(connection, payer.payer, pool.mintToken1, pool.token1Account, payer.publicKey, donation)
+        await mintTo(connection, payer.payer, pool.mintToken2, pool.token2Account, payer.publicKey, donation)
+        try {
+            await program.methods
+                .sync()
+                .accounts({
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1: pool.mintToken1,
+                    mintToken2: pool.mintToken2,
+                })
+                .rpc()
+            assert.fail("sync should have failed")
+        } catch (err) {
+            assert.strictEqual(err.error?.errorCode?.code, "PoolEmpty")
+        }
+
+        // the donation did not become reserves, so the first deposit still goes through
+        const token1Amount = 1_000_000
+        const token2Amount = 4_000_000
+        await mintTo(connection, payer.payer, pool.mintToken1, pool.userToken1, payer.publicKey, token1Amount)
+        await mintTo(connection, payer.payer, pool.mintToken2, pool.userToken2, payer.publicKey, token2Amount)
+        await program.methods
+            .addLiquidity(new anchor.BN(token1Amount), new anchor.BN(token2Amount), new anchor.BN(0), null)
+            .accounts({
+                signer: payer.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                mintToken1: pool.mintToken1,
+                mintToken2: pool.mintToken2,
+            })
+            .rpc()
+        const dataAccount = await program.account.dataAccount.fetch(pool.dataAccount)
+        assert.strictEqual(dataAccount.token1Balance.toNumber(), token1Amount)
+        assert.strictEqual(dataAccount.token2Balance.toNumber(), token2Amount)
+        const lpSupply = Number((await getMint(connection, pool.lpMint)).supply)
+        assert.strictEqual(lpSupply, Math.floor(Math.sqrt(token1Amount * token2Amount)))
+    })
+
+    describe("TWAP over a small observation buffer", () => {
+        let pool: Awaited<ReturnType<typeof createPool>>
+        let observationsPda: anchor.web3.PublicKey
+        const spotPrices: bigint[] = []
+
+        const swapOnPool = async (amount: number, mint: anchor.web3.PublicKey) => {
+            await program.methods
+                .swap(new anchor.BN(amount), mint, new anchor.BN(0), null)
+                .accounts({
+                    signer: payer.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1: pool.mintToken1,
+                    mintToken2: pool.mintToken2,
+                })
+                .rpc()
+            const dataAccount = await program.account.dataAccount.fetch(pool.dataAccount)
+            spotPrices.push(q64Price(dataAccount.token2Balance, dataAccount.token1Balance))
+        }
+
+        before(async () => {
+            // room for two samples, so a handful of swaps wraps it
+            pool = await createPool(2)
+            observationsPda = anchor.web3.PublicKey.findProgramAddressSync(
+                [Buffer.from("observations"), pool.dataAccount.toBuffer()],
+                program.programId
+            )[0]
+            await mintTo(connection, payer.payer, pool.mintToken1, pool.userToken1, payer.publicKey, 2_000_000)
+            await mintTo(connection, payer.payer, pool.mintToken2, pool.userToken2, payer.publicKey, 5_000_000)
+            await program.methods
+                .addLiquidity(new anchor.BN(1_000_000), new anchor.BN(4_000_000), new anchor.BN(0), null)
+                .accounts({
+                    signer: payer.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    mintToken1: pool.mintToken1,
+                    mintToken2: pool.mintToken2,
+                })
+                .rpc()
+            const dataAccount = await program.account.dataAccount.fetch(pool.dataAccount)
+            spotPrices.push(q64Price(dataAccount.token2Balance, dataAccount.token1Balance))
+            // every swap lands a second or more after the previous one, each writing its own sample
+            for (const [amount, mint] of [
+                [200_000, pool.mintToken1],
+                [300_000, pool.mintToken2],
+                [100_000, pool.mintToken1],
+            ] as [number, anchor.web3.PublicKey][]) {
+                await sleep(2_000)
+                await swapOnPool(amount, mint)
+            }
+            await sleep(2_000)
+        })
+
+        it("Wraps the buffer and keeps only the latest samples", async () => {
+            const observations = await program.account.observations.fetch(observationsPda)
+            assert.strictEqual(observations.observations.length, 2)
+            const latest = observations.observations[observations.index]
+            const oldest = observations.observations[(observations.index + 1) % 2]
+            const dataAccount = await program.account.dataAccount.fetch(pool.dataAccount)
+            assert.ok(latest.timestamp.eq(dataAccount.lastPriceUpdate), "The latest sample is the last swap")
+            assert.ok(oldest.timestamp.lt(latest.timestamp))
+            assert.strictEqual(latest.price1Cumulative.toString(), dataAccount.price1Cumulative.toString())
+        })
+
+        it("Averages the price over swaps in separate slots", async () => {
+            // a window inside the time since the last swap sees only the current price
+            const recent = await program.methods
+                .getTwap(1)
+                .accounts({ dataAccount: pool.dataAccount })
+                .view()
+            assert.strictEqual(BigInt(recent.price1.toString()), spotPrices[spotPrices.length - 1])
+
+            // a window back to the oldest sample averages the prices that held across it
+            const observations = await program.account.observations.fetch(observationsPda)
+            const latest = observations.observations[observations.index]
+            const oldest = observations.observations[(observations.index + 1) % 2]
+            const window = latest.timestamp.sub(oldest.timestamp).toNumber()
+            const twap = await program.methods
+                .getTwap(window)
+                .accounts({ dataAccount: pool.dataAccount })
+                .view()
+            const price1 = BigInt(twap.price1.toString())
+            const lowest = spotPrices.reduce((a, b) => (a < b ? a : b))
+            const highest = spotPrices.reduce((a, b) => (a > b ? a : b))
+            assert.ok(price1 >= lowest && price1 <= highest, "The average stays within the prices that held")
+        })
+
+        it("Rejects a window older than the oldest sample", async () => {
+            try {
+                await program.methods
+                    .getTwap(3_600)
+                    .accounts({ dataAccount: pool.dataAccount })
+                    .view()
+                assert.fail("getTwap should have failed")
+            } catch (err) {
+                assert.include(err.toString(), "NotEnoughObservations")
+            }
+        })
+    })
+})